@@ -11,6 +11,9 @@ use contrail::{
     Array, Trail, TrailBuilder,
 };
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 const BLOCK_SIZE: u64 = 64;
 
 /// A bit set stored in backtrackable storage on the trail.
@@ -20,6 +23,7 @@ pub type NonBacktrackableBitSet = BitSet<NonBacktrackable>;
 
 /// A bit set.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
 pub struct BitSet<M> {
     blocks: Array<M, u64>,
     max: u64,
@@ -118,9 +122,7 @@ where
     /// assert!(!bit_set.contains(&trail, 42));
     /// ```
     pub fn clear(&self, trail: &mut Trail) {
-        for i in 0..self.blocks.len() {
-            self.blocks.set(trail, i, 0);
-        }
+        self.blocks.fill(trail, 0);
     }
 
     /// Inserts an element in the bit set.
@@ -332,7 +334,7 @@ mod tests {
         let empty = BacktrackableBitSet::new_empty(&mut builder, 10);
         let full = BacktrackableBitSet::new_full(&mut builder, 10);
         let trail = builder.finish();
-        
+
         assert_eq!(empty.capacity(), 10);
         assert_eq!(full.capacity(), 10);
 
@@ -366,7 +368,7 @@ mod tests {
         let mut trail = builder.finish();
 
         assert!(!bit_set.contains(&trail, 100));
-        
+
         for i in 0..10 {
             assert!(!bit_set.contains(&trail, i));
             bit_set.insert(&mut trail, i);
@@ -425,4 +427,51 @@ mod tests {
             assert_eq!(bit_set.next_above(&trail, i), None);
         }
     }
+
+    #[test]
+    fn user_defined_storage_mode() {
+        use contrail::storage::Undo;
+
+        #[derive(Clone, Copy, Debug, Default)]
+        struct Logged;
+
+        impl StorageMode for Logged {
+            const NAME: &'static str = "Logged";
+            type Policy = Undo;
+        }
+
+        let mut builder = TrailBuilder::new();
+        let bit_set = BitSet::<Logged>::new_empty(&mut builder, 100);
+        let mut trail = builder.finish();
+
+        bit_set.insert(&mut trail, 3);
+        trail.new_level();
+        bit_set.insert(&mut trail, 70);
+        bit_set.remove(&mut trail, 3);
+        trail.backtrack();
+
+        assert!(bit_set.contains(&trail, 3));
+        assert!(!bit_set.contains(&trail, 70));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut builder = TrailBuilder::new();
+        let bit_set = BacktrackableBitSet::new_empty(&mut builder, 100);
+        let mut trail = builder.finish();
+        bit_set.insert(&mut trail, 3);
+        trail.new_level();
+        bit_set.insert(&mut trail, 70);
+
+        let json = serde_json::to_string(&(&trail, bit_set)).unwrap();
+        let (mut trail, bit_set): (Trail, BacktrackableBitSet) =
+            serde_json::from_str(&json).unwrap();
+
+        assert!(bit_set.contains(&trail, 3));
+        assert!(bit_set.contains(&trail, 70));
+        trail.backtrack();
+        assert!(bit_set.contains(&trail, 3));
+        assert!(!bit_set.contains(&trail, 70));
+    }
 }
@@ -6,6 +6,13 @@
 
 //! Collections built on top of `contrail`.
 
+// the collections were written before these lints were added
+#![allow(
+    clippy::clone_on_copy,
+    clippy::non_canonical_clone_impl,
+    clippy::unnecessary_cast
+)]
+
 pub mod bit_set;
 pub mod linked_list;
 pub mod sparse_set;
@@ -13,6 +13,9 @@ use contrail::{
 };
 use std::fmt;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// A linked list arena stored on the trail in backtrackable memory.
 pub type BacktrackableLinkedListArena<T> = LinkedListArena<Backtrackable, T>;
 /// A linked list arena stored on the trail in non-backtrackable memory.
@@ -23,6 +26,7 @@ pub type BacktrackableLinkedListNode<T> = LinkedListNode<Backtrackable, T>;
 pub type NonBacktrackableLinkedListNode<T> = LinkedListNode<NonBacktrackable, T>;
 
 /// An arena that holds linked list nodes.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
 pub struct LinkedListArena<M, T> {
     prev: Array<M, usize>,
     next: Array<M, usize>,
@@ -136,6 +140,7 @@ where
 }
 
 /// A node in a linked list.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
 pub struct LinkedListNode<M, T> {
     prev: Array<M, usize>,
     next: Array<M, usize>,
@@ -12,6 +12,9 @@ use contrail::{
 };
 use std::fmt;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// A sparse set stored on the trail in backtrackable memory.
 pub type BacktrackableSparseSet = SparseSet<Backtrackable>;
 
@@ -22,6 +25,7 @@ pub type NonBacktrackableSparseSet = SparseSet<NonBacktrackable>;
 /// only decrease in size.
 ///
 /// Features O(1) `contains()` and `remove()` as well as fast value iteration.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
 pub struct SparseSet<M> {
     values: NonBacktrackableArray<usize>,
     positions: NonBacktrackableArray<usize>,
@@ -76,7 +80,7 @@ where
     /// }
     /// ```
     pub fn iter<'s, 't: 's>(&'s self, trail: &'t Trail) -> impl Iterator<Item = usize> + 's {
-        (0..self.len.get(trail)).map(move |i| self.values.get(trail, i))
+        self.values.slice(..self.len.get(trail)).iter(trail)
     }
 
     /// Returns the length of the `SparseSet`.
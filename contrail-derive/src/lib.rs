@@ -48,7 +48,27 @@ pub fn bytes_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 // safe assuming that the length of the byte slice is Self::LENGTH.
                 bytes.copy_from_slice(&byte_array);
             }
+
+            #[inline(always)]
+            unsafe fn read_aligned(bytes: &[u8]) -> #name {
+                // safe assuming the same as `read_bytes` and that the byte slice is aligned.
+                std::ptr::read(bytes.as_ptr() as *const #name)
+            }
+
+            #[inline(always)]
+            unsafe fn write_aligned(self, bytes: &mut [u8]) {
+                // the write below doesn't necessarily set padding bytes, so the bytes are zeroed
+                // first rather than keeping whatever the memory held before.
+                for byte in bytes.iter_mut() {
+                    *byte = 0;
+                }
+                // safe assuming the same as `write_bytes` and that the byte slice is aligned.
+                std::ptr::write(bytes.as_mut_ptr() as *mut #name, self);
+            }
         }
+
+        // safe because the bytes written are the value transmuted to a byte array
+        unsafe impl contrail::mem::Plain for #name {}
     };
 
     impl_tokens.into()
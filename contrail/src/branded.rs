@@ -0,0 +1,721 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public License,
+ * v. 2.0. If a copy of the MPL was not distributed with this file, You can
+ * obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Trails whose values are tied to them at compile time.
+//!
+//! A branded trail is created with [`TrailBuilder::scope`](crate::TrailBuilder::scope), which
+//! passes a [`TrailBuilder<'brand>`](TrailBuilder) to a closure. The lifetime `'brand` is unique
+//! to that call, and it's shared by the finished [`Trail<'brand>`](Trail) and by every
+//! [`Value<'brand, M, T>`](Value) and [`Array<'brand, M, T>`](Array) created from the builder.
+//! Because the brand is invariant and can't escape the closure, values from one branded trail
+//! can't be used with any other trail:
+//!
+//! ```compile_fail
+//! use contrail::{branded::BacktrackableValue, TrailBuilder};
+//!
+//! TrailBuilder::new().scope(|mut builder_a| {
+//!     let value_a = BacktrackableValue::new(&mut builder_a, 1);
+//!     let _trail_a = builder_a.finish();
+//!
+//!     TrailBuilder::new().scope(|builder_b| {
+//!         let trail_b = builder_b.finish();
+//!         // error: the brands of `value_a` and `trail_b` are different
+//!         value_a.get(&trail_b);
+//!     });
+//! });
+//! ```
+//!
+//! A branded trail never gives out a `&mut` reference to the trail it wraps, so the underlying
+//! trail can't be swapped with another one behind the brand's back.
+//!
+//! # Examples
+//!
+//! ```
+//! use contrail::{branded::BacktrackableValue, TrailBuilder};
+//!
+//! let result = TrailBuilder::new().scope(|mut builder| {
+//!     let value = BacktrackableValue::new(&mut builder, 1);
+//!     let mut trail = builder.finish();
+//!
+//!     trail.new_level();
+//!     value.set(&mut trail, 2);
+//!     trail.backtrack();
+//!
+//!     value.get(&trail)
+//! });
+//!
+//! assert_eq!(result, 1);
+//! ```
+
+use std::{
+    collections::vec_deque,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, RangeBounds},
+};
+
+use crate::{
+    mem::{Bytes, Plain},
+    storage::{Backtrackable, Constant, NonBacktrackable, Readable, Restart, StorageMode},
+    watch::{Event, EventKind, WatchId},
+    ArrayIter, BacktrackAction, Level,
+};
+
+/// An invariant lifetime that ties branded values to their trail.
+type Brand<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// A trail builder with a unique brand.
+///
+/// Created by [`TrailBuilder::scope`](crate::TrailBuilder::scope).
+pub struct TrailBuilder<'brand> {
+    builder: crate::TrailBuilder,
+    brand: Brand<'brand>,
+}
+
+impl<'brand> TrailBuilder<'brand> {
+    pub(crate) fn new(builder: crate::TrailBuilder) -> Self {
+        Self {
+            builder,
+            brand: PhantomData,
+        }
+    }
+
+    /// Consumes the `TrailBuilder` to create a new `Trail` with the same brand.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{branded::NonBacktrackableValue, TrailBuilder};
+    ///
+    /// TrailBuilder::new().scope(|mut builder| {
+    ///     let value = NonBacktrackableValue::new(&mut builder, -123);
+    ///     let trail = builder.finish();
+    ///
+    ///     assert_eq!(value.get(&trail), -123);
+    /// });
+    /// ```
+    pub fn finish(self) -> Trail<'brand> {
+        Trail {
+            trail: self.builder.finish(),
+            brand: self.brand,
+        }
+    }
+}
+
+impl<'brand> fmt::Debug for TrailBuilder<'brand> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrailBuilder")
+            .field("builder", &self.builder)
+            .finish()
+    }
+}
+
+/// A trail with a unique brand.
+///
+/// The branded trail dereferences to the underlying [`Trail`](crate::Trail) for read-only access.
+/// Methods that modify the trail are forwarded individually.
+#[repr(transparent)]
+pub struct Trail<'brand> {
+    trail: crate::Trail,
+    brand: Brand<'brand>,
+}
+
+impl<'brand> Trail<'brand> {
+    /// Adds a new level to the trail.
+    ///
+    /// See [`Trail::new_level`](crate::Trail::new_level).
+    pub fn new_level(&mut self) -> Level {
+        self.trail.new_level()
+    }
+
+    /// Backtracks the trail to the most recent level.
+    ///
+    /// See [`Trail::backtrack`](crate::Trail::backtrack).
+    pub fn backtrack(&mut self) {
+        self.trail.backtrack();
+    }
+
+    /// Backtracks the trail to the most recent level, passing `ext` to the actions registered
+    /// with [`on_backtrack_with`](Trail::on_backtrack_with).
+    ///
+    /// See [`Trail::backtrack_with`](crate::Trail::backtrack_with).
+    pub fn backtrack_with<E: 'static>(&mut self, ext: &mut E) {
+        self.trail.backtrack_with(ext);
+    }
+
+    /// Removes the most recent level from the trail while keeping its changes.
+    ///
+    /// See [`Trail::commit_level`](crate::Trail::commit_level).
+    pub fn commit_level(&mut self) {
+        self.trail.commit_level();
+    }
+
+    /// Backtracks the trail until the given level is removed.
+    ///
+    /// See [`Trail::backtrack_to`](crate::Trail::backtrack_to).
+    pub fn backtrack_to(&mut self, level: Level) {
+        self.trail.backtrack_to(level);
+    }
+
+    /// Backtracks the trail until the given level is removed, passing `ext` to the actions
+    /// registered with [`on_backtrack_with`](Trail::on_backtrack_with).
+    ///
+    /// See [`Trail::backtrack_to_with`](crate::Trail::backtrack_to_with).
+    pub fn backtrack_to_with<E: 'static>(&mut self, level: Level, ext: &mut E) {
+        self.trail.backtrack_to_with(level, ext);
+    }
+
+    /// Backtracks the trail until every level is removed.
+    ///
+    /// See [`Trail::reset_to_root`](crate::Trail::reset_to_root).
+    pub fn reset_to_root(&mut self) {
+        self.trail.reset_to_root();
+    }
+
+    /// Restarts the trail, removing every level and resetting the restart memory.
+    ///
+    /// See [`Trail::restart`](crate::Trail::restart).
+    pub fn restart(&mut self) {
+        self.trail.restart();
+    }
+
+    /// Resets the statistics of the trail.
+    ///
+    /// See [`Trail::reset_stats`](crate::Trail::reset_stats).
+    pub fn reset_stats(&mut self) {
+        self.trail.reset_stats();
+    }
+
+    /// Creates a copy of the trail with the same brand that shares its constant memory.
+    ///
+    /// See [`Trail::fork`](crate::Trail::fork).
+    pub fn fork(&self) -> Trail<'brand> {
+        Trail {
+            trail: self.trail.fork(),
+            brand: self.brand,
+        }
+    }
+
+    /// Registers an action to run when the most recent level is removed by backtracking.
+    ///
+    /// Unlike the unbranded trail, the action may capture branded values.
+    ///
+    /// See [`Trail::on_backtrack`](crate::Trail::on_backtrack).
+    pub fn on_backtrack(&mut self, action: impl FnOnce(&mut Self) + Send + Sync + 'brand) {
+        self.push_backtrack_action(Box::new(move |trail, _| action(Self::wrap(trail))));
+    }
+
+    /// Registers an action over external state to run when the most recent level is removed by
+    /// backtracking.
+    ///
+    /// Unlike the unbranded trail, the action may capture branded values.
+    ///
+    /// See [`Trail::on_backtrack_with`](crate::Trail::on_backtrack_with).
+    pub fn on_backtrack_with<E: 'static>(
+        &mut self,
+        action: impl FnOnce(&mut Self, &mut E) + Send + Sync + 'brand,
+    ) {
+        self.push_backtrack_action(Box::new(move |trail, ext| {
+            action(Self::wrap(trail), crate::downcast_ext(ext))
+        }));
+    }
+
+    /// Registers an action, which may capture branded values, with the wrapped trail.
+    fn push_backtrack_action(&mut self, action: BacktrackAction<'brand>) {
+        // SAFETY: the action is stored in the trail wrapped by this branded trail, which can't be
+        // used outside of `'brand`, and actions are never moved to another trail, so the action
+        // is run or dropped before anything it borrows goes away.
+        let action: BacktrackAction<'static> = unsafe { std::mem::transmute(action) };
+        self.trail.push_backtrack_action(action);
+    }
+
+    /// Stops the given watch.
+    ///
+    /// See [`Trail::unwatch`](crate::Trail::unwatch).
+    pub fn unwatch(&mut self, watch: WatchId) -> bool {
+        self.trail.unwatch(watch)
+    }
+
+    /// Removes the oldest event from the queue of events fired by watches.
+    ///
+    /// See [`Trail::next_event`](crate::Trail::next_event).
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.trail.next_event()
+    }
+
+    /// Removes every event from the queue of events fired by watches.
+    ///
+    /// See [`Trail::drain_events`](crate::Trail::drain_events).
+    pub fn drain_events(&mut self) -> vec_deque::Drain<'_, Event> {
+        self.trail.drain_events()
+    }
+
+    /// Runs the given function at a new level, then backtracks until the level is removed.
+    ///
+    /// See [`Trail::probe`](crate::Trail::probe).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{branded::BacktrackableValue, TrailBuilder};
+    ///
+    /// TrailBuilder::new().scope(|mut builder| {
+    ///     let value = BacktrackableValue::new(&mut builder, 3);
+    ///     let mut trail = builder.finish();
+    ///
+    ///     let squared = trail.probe(|trail| {
+    ///         value.update(trail, |x| x * x);
+    ///         value.get(trail)
+    ///     });
+    ///
+    ///     assert_eq!(squared, 9);
+    ///     assert_eq!(value.get(&trail), 3);
+    /// });
+    /// ```
+    pub fn probe<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.trail.probe(|trail| f(Self::wrap(trail)))
+    }
+
+    /// Runs the given function at a new level, then commits the level if the function succeeds
+    /// or backtracks until the level is removed if it fails.
+    ///
+    /// See [`Trail::transaction`](crate::Trail::transaction).
+    pub fn transaction<R, E>(&mut self, f: impl FnOnce(&mut Self) -> Result<R, E>) -> Result<R, E> {
+        self.trail.transaction(|trail| f(Self::wrap(trail)))
+    }
+
+    /// Views the trail wrapped by a `Trail<'brand>` as the branded trail again.
+    fn wrap(trail: &mut crate::Trail) -> &mut Self {
+        // SAFETY: `Trail<'brand>` is a transparent wrapper around `crate::Trail`, and `trail`
+        // is always the trail wrapped by a `Trail<'brand>`, so the brand is preserved.
+        unsafe { &mut *(trail as *mut crate::Trail as *mut Self) }
+    }
+}
+
+impl<'brand> Deref for Trail<'brand> {
+    type Target = crate::Trail;
+
+    fn deref(&self) -> &crate::Trail {
+        &self.trail
+    }
+}
+
+impl<'brand> fmt::Debug for Trail<'brand> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Trail")
+            .field("trail_len", &self.trail.trail_len())
+            .field("strategy", &self.trail.strategy())
+            .finish()
+    }
+}
+
+/// A reference to a value stored on a branded trail.
+///
+/// See [`Value`](crate::Value).
+pub struct Value<'brand, M, T> {
+    value: crate::Value<M, T>,
+    brand: Brand<'brand>,
+}
+
+impl<'brand, M, T> Value<'brand, M, T>
+where
+    M: Readable,
+    T: Bytes,
+{
+    /// Creates a new `Value` with the given value.
+    ///
+    /// The `Value` is usable with the `Trail` created from the builder.
+    pub fn new(builder: &mut TrailBuilder<'brand>, val: T) -> Self {
+        Self {
+            value: crate::Value::new(&mut builder.builder, val),
+            brand: PhantomData,
+        }
+    }
+
+    /// Creates a new named `Value` with the given value.
+    ///
+    /// See [`Value::named`](crate::Value::named).
+    pub fn named(builder: &mut TrailBuilder<'brand>, name: impl Into<String>, val: T) -> Self
+    where
+        T: fmt::Debug,
+    {
+        Self {
+            value: crate::Value::named(&mut builder.builder, name, val),
+            brand: PhantomData,
+        }
+    }
+
+    /// Gets the value from the trail.
+    #[inline]
+    pub fn get(self, trail: &Trail<'brand>) -> T {
+        self.value.get(&trail.trail)
+    }
+}
+
+impl<'brand, M, T> Value<'brand, M, T>
+where
+    M: StorageMode,
+    T: Bytes,
+{
+    /// Sets the value on the trail.
+    #[inline]
+    pub fn set(self, trail: &mut Trail<'brand>, new_val: T) {
+        self.value.set(&mut trail.trail, new_val);
+    }
+
+    /// Updates the value on the trail using the given function.
+    #[inline]
+    pub fn update(self, trail: &mut Trail<'brand>, f: impl FnOnce(T) -> T) {
+        self.value.update(&mut trail.trail, f);
+    }
+
+    /// Watches the value, so that writes to it add an event to the trail's queue of events.
+    ///
+    /// See [`Value::watch`](crate::Value::watch).
+    pub fn watch(self, trail: &mut Trail<'brand>, kind: EventKind) -> WatchId {
+        self.value.watch(&mut trail.trail, kind)
+    }
+}
+
+impl<'brand, M, T> Clone for Value<'brand, M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'brand, M, T> Copy for Value<'brand, M, T> {}
+
+impl<'brand, M, T> fmt::Debug for Value<'brand, M, T>
+where
+    M: Readable,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+impl<'brand, M, T> Eq for Value<'brand, M, T> {}
+
+impl<'brand, M, T> PartialEq for Value<'brand, M, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+/// A reference to a fixed-length array of values stored on a branded trail.
+///
+/// See [`Array`](crate::Array).
+pub struct Array<'brand, M, T> {
+    array: crate::Array<M, T>,
+    brand: Brand<'brand>,
+}
+
+impl<'brand, M, T> Array<'brand, M, T>
+where
+    M: Readable,
+    T: Bytes,
+{
+    /// Creates a new `Array` with the given values.
+    ///
+    /// The `Array` is usable with the `Trail` created from the builder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{branded::BacktrackableArray, TrailBuilder};
+    ///
+    /// TrailBuilder::new().scope(|mut builder| {
+    ///     let array = BacktrackableArray::new(&mut builder, 0..4);
+    ///     let mut trail = builder.finish();
+    ///
+    ///     array.swap(&mut trail, 0, 3);
+    ///     assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![3, 1, 2, 0]);
+    /// });
+    /// ```
+    pub fn new(builder: &mut TrailBuilder<'brand>, vals: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            array: crate::Array::new(&mut builder.builder, vals),
+            brand: PhantomData,
+        }
+    }
+
+    /// Creates a new named `Array` with the given values.
+    ///
+    /// See [`Array::named`](crate::Array::named).
+    pub fn named(
+        builder: &mut TrailBuilder<'brand>,
+        name: impl Into<String>,
+        vals: impl IntoIterator<Item = T>,
+    ) -> Self
+    where
+        T: fmt::Debug,
+    {
+        Self {
+            array: crate::Array::named(&mut builder.builder, name, vals),
+            brand: PhantomData,
+        }
+    }
+
+    /// Returns the length of the array.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    /// Checks if the length of the array is equal to 0.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.array.is_empty()
+    }
+
+    /// Returns an iterator over the elements of the array.
+    pub fn iter<'t>(&self, trail: &'t Trail<'brand>) -> ArrayIter<'t, M, T> {
+        self.array.iter(&trail.trail)
+    }
+
+    /// Gets the value of the array at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    #[inline]
+    pub fn get(&self, trail: &Trail<'brand>, i: usize) -> T {
+        self.array.get(&trail.trail, i)
+    }
+
+    /// Returns the values of the array as a slice.
+    ///
+    /// See [`Array::as_slice`](crate::Array::as_slice).
+    #[inline]
+    pub fn as_slice<'t>(&self, trail: &'t Trail<'brand>) -> &'t [T]
+    where
+        T: Plain,
+    {
+        self.array.as_slice(&trail.trail)
+    }
+
+    /// Returns an `Array` of the values in the given range of the array.
+    ///
+    /// See [`Array::slice`](crate::Array::slice).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        Self {
+            array: self.array.slice(range),
+            brand: PhantomData,
+        }
+    }
+
+    /// Copies the values of the array to the given slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is different from the length of the array.
+    #[inline]
+    pub fn read_into(&self, trail: &Trail<'brand>, out: &mut [T]) {
+        self.array.read_into(&trail.trail, out);
+    }
+}
+
+impl<'brand, M, T> Array<'brand, M, T>
+where
+    M: StorageMode,
+    T: Bytes,
+{
+    /// Sets the value of the array at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    #[inline]
+    pub fn set(&self, trail: &mut Trail<'brand>, i: usize, new_val: T) {
+        self.array.set(&mut trail.trail, i, new_val);
+    }
+
+    /// Updates the value of the array at the given index using the given update function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    #[inline]
+    pub fn update(&self, trail: &mut Trail<'brand>, i: usize, f: impl FnOnce(T) -> T) {
+        self.array.update(&mut trail.trail, i, f);
+    }
+
+    /// Swaps the two values at the given indices of the array in memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either of the indices are out of bounds.
+    #[inline]
+    pub fn swap(&self, trail: &mut Trail<'brand>, i: usize, j: usize) {
+        self.array.swap(&mut trail.trail, i, j);
+    }
+
+    /// Sets the values of the array to the values of the given slice.
+    ///
+    /// See [`Array::write_from`](crate::Array::write_from).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is different from the length of the array.
+    #[inline]
+    pub fn write_from(&self, trail: &mut Trail<'brand>, vals: &[T]) {
+        self.array.write_from(&mut trail.trail, vals);
+    }
+
+    /// Sets every value of the array to the given value.
+    #[inline]
+    pub fn fill(&self, trail: &mut Trail<'brand>, val: T) {
+        self.array.fill(&mut trail.trail, val);
+    }
+
+    /// Copies the values in the range `src` of the array to the indices starting at `dest`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either range is out of bounds.
+    pub fn copy_within(
+        &self,
+        trail: &mut Trail<'brand>,
+        src: impl RangeBounds<usize>,
+        dest: usize,
+    ) {
+        self.array.copy_within(&mut trail.trail, src, dest);
+    }
+
+    /// Updates every value of the array in place using the given function.
+    #[inline]
+    pub fn for_each_mut(&self, trail: &mut Trail<'brand>, f: impl FnMut(&mut T)) {
+        self.array.for_each_mut(&mut trail.trail, f);
+    }
+
+    /// Watches the value of the array at the given index, so that writes to it add an event to
+    /// the trail's queue of events.
+    ///
+    /// See [`Array::watch`](crate::Array::watch).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn watch(&self, trail: &mut Trail<'brand>, i: usize, kind: EventKind) -> WatchId {
+        self.array.watch(&mut trail.trail, i, kind)
+    }
+}
+
+impl<'brand, M, T> Clone for Array<'brand, M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'brand, M, T> Copy for Array<'brand, M, T> {}
+
+impl<'brand, M, T> fmt::Debug for Array<'brand, M, T>
+where
+    M: Readable,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.array.fmt(f)
+    }
+}
+
+impl<'brand, M, T> Eq for Array<'brand, M, T> {}
+
+impl<'brand, M, T> PartialEq for Array<'brand, M, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.array == other.array
+    }
+}
+
+/// A value stored on a branded trail in backtrackable memory.
+pub type BacktrackableValue<'brand, T> = Value<'brand, Backtrackable, T>;
+
+/// A value stored on a branded trail in non-backtrackable memory.
+pub type NonBacktrackableValue<'brand, T> = Value<'brand, NonBacktrackable, T>;
+
+/// A fixed-length array stored on a branded trail in backtrackable memory.
+pub type BacktrackableArray<'brand, T> = Array<'brand, Backtrackable, T>;
+
+/// A fixed-length array stored on a branded trail in non-backtrackable memory.
+pub type NonBacktrackableArray<'brand, T> = Array<'brand, NonBacktrackable, T>;
+
+/// A value stored on a branded trail in constant memory.
+pub type ConstantValue<'brand, T> = Value<'brand, Constant, T>;
+
+/// A fixed-length array stored on a branded trail in constant memory.
+pub type ConstantArray<'brand, T> = Array<'brand, Constant, T>;
+
+/// A value stored on a branded trail in restart memory.
+pub type RestartValue<'brand, T> = Value<'brand, Restart, T>;
+
+/// A fixed-length array stored on a branded trail in restart memory.
+pub type RestartArray<'brand, T> = Array<'brand, Restart, T>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transaction() {
+        crate::TrailBuilder::new().scope(|mut builder| {
+            let value = BacktrackableValue::new(&mut builder, 10);
+            let array = NonBacktrackableArray::new(&mut builder, vec![0; 3]);
+            let mut trail = builder.finish();
+
+            let result: Result<(), ()> = trail.transaction(|trail| {
+                value.set(trail, 20);
+                array.set(trail, 1, 1);
+                Err(())
+            });
+            assert!(result.is_err());
+            assert_eq!(value.get(&trail), 10);
+            assert_eq!(array.get(&trail, 1), 1);
+
+            let result: Result<(), ()> = trail.transaction(|trail| {
+                value.set(trail, 30);
+                Ok(())
+            });
+            assert!(result.is_ok());
+            assert_eq!(value.get(&trail), 30);
+            assert!(trail.is_trail_empty());
+        });
+    }
+
+    #[test]
+    fn on_backtrack() {
+        crate::TrailBuilder::new().scope(|mut builder| {
+            let value = BacktrackableValue::new(&mut builder, 10);
+            let seen = NonBacktrackableValue::new(&mut builder, 0);
+            let mut trail = builder.finish();
+
+            trail.new_level();
+            value.set(&mut trail, 20);
+            trail.on_backtrack(move |trail| seen.set(trail, value.get(trail)));
+            trail.backtrack();
+
+            assert_eq!(seen.get(&trail), 10);
+        });
+    }
+
+    #[test]
+    fn on_backtrack_with() {
+        crate::TrailBuilder::new().scope(|mut builder| {
+            let value = BacktrackableValue::new(&mut builder, 10);
+            let mut trail = builder.finish();
+            let mut seen: Vec<i32> = vec![];
+
+            let level = trail.new_level();
+            value.set(&mut trail, 20);
+            trail.on_backtrack_with(move |trail, seen: &mut Vec<i32>| seen.push(value.get(trail)));
+            trail.backtrack_to_with(level, &mut seen);
+
+            assert_eq!(seen, vec![10]);
+        });
+    }
+}
@@ -0,0 +1,957 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public License,
+ * v. 2.0. If a copy of the MPL was not distributed with this file, You can
+ * obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Saving trails to disk and loading them back.
+//!
+//! A trail is saved with [`Trail::save`](crate::Trail::save) and loaded with
+//! [`Trail::load`](crate::Trail::load). The backtrackable, non-backtrackable and restart
+//! memories are saved along with the whole stack of levels and the trail's strategy.
+//!
+//! # Format
+//!
+//! All integers in the header and in the level stack are stored in little-endian order, and
+//! lengths and offsets are stored as `u64`. A checkpoint consists of the following, in order:
+//!
+//! | Field                        | Contents                                                     |
+//! |------------------------------|--------------------------------------------------------------|
+//! | magic                        | the 8 bytes `CONTRAIL`                                       |
+//! | version                      | `u32`, currently [`VERSION`](VERSION)                        |
+//! | endianness                   | `u8`, 0 for little-endian and 1 for big-endian               |
+//! | pointer width                | `u8`, the size of `usize` in bytes                           |
+//! | strategy                     | `u8`, 0 for copy, 1 for log, 2 for paged, 3 for adaptive and |
+//! |                              | 4 for stamped                                                |
+//! | last level written           | `u64`, the number of bytes written at the last removed level |
+//! | next level id                | `u64`                                                        |
+//! | backtrackable memory         | see below                                                    |
+//! | non-backtrackable memory     | see below                                                    |
+//! | restart memory               | see below                                                    |
+//! | levels                       | `u64` count, then each level as described below             |
+//! | checksum                     | `u64`, the 64-bit FNV-1a hash of everything before it        |
+//!
+//! Each memory is stored as its layout size (the number of bytes added by the `TrailBuilder` and
+//! by [`Trail::extend`](crate::Trail::extend)), its length, its bytes, its write counter, the
+//! number of undo log entries, the offset and length of each entry, the overwritten bytes of
+//! every entry, and finally its allocations. Each level is stored as its id, its undo log mark,
+//! the length of the backtrackable memory when it was added, what it records in the undo log (0
+//! for nothing, 1 for writes, 2 for pages and 3 for words), the number of bytes written at the
+//! level, the write counter when it was resumed, a `u8` flag followed by the length and bytes of
+//! its copy of the backtrackable memory if the flag is 1, and a `u8` flag followed by the
+//! allocations to put back when the level is removed if the flag is 1. A list of allocations is
+//! stored as its length, then the end offset and generation of each allocation that hasn't been
+//! freed, oldest first.
+//!
+//! The bytes of the memories are stored as they are in memory, using the endianness and pointer
+//! width recorded in the header, so a checkpoint can only be loaded on a platform where both
+//! match.
+//!
+//! Only checkpoints of the current version can be loaded. The versions of the format are:
+//!
+//! | Version | Changes                                                              |
+//! |---------|----------------------------------------------------------------------|
+//! | 1       | the initial format                                                   |
+//! | 2       | adds the stamped strategy (4) and levels that record words (3)       |
+//! | 3       | adds the restart memory                                              |
+//! | 4       | adds allocations and leaves them out of the layout size              |
+//!
+//! Values and arrays allocated with [`Value::alloc`](crate::Value::alloc) and
+//! [`Array::alloc`](crate::Array::alloc) are saved and stay allocated in the loaded trail, but
+//! a `TrailBuilder` can't recreate handles to them. Handles to allocated values can be serialized
+//! along with a trail instead (see below).
+//!
+//! Constant memory and the memories of [user-defined storage modes](crate::storage) aren't saved:
+//! a loaded trail starts them over from the values created with its `TrailBuilder`. A
+//! [restart](crate::Trail::restart) of the loaded trail resets the restart memory to the values
+//! created with its `TrailBuilder`.
+//!
+//! Neither checkpoints nor serialized trails include the trail's statistics, its watches and
+//! their events, or the actions registered with [`on_backtrack`](crate::Trail::on_backtrack).
+//!
+//! When the `serde` feature is enabled, `Trail` also implements serde's `Serialize` and
+//! `Deserialize`. A serialized trail holds everything a checkpoint does, along with the constant
+//! memory and the values the restart memory is reset to, so deserializing a trail doesn't need a
+//! `TrailBuilder` and handles can be serialized along with the trail. A deserialized trail has no
+//! memories for user-defined storage modes, so their values can't be used with it, and it doesn't
+//! keep the names of [named values](crate::layout).
+//!
+//! # Trusted input
+//!
+//! The bytes of a loaded or deserialized memory are used as they are, and so is the offset of a
+//! deserialized handle, so reading a value whose bytes don't hold a valid value of its type, such
+//! as a `bool` stored as 2, is undefined behavior. The checksum and the checks made while loading
+//! catch accidental corruption and mistakes such as deserializing a handle as a handle to another
+//! type, but not deliberate tampering, so only load checkpoints and deserialize trails, memories
+//! and handles that come from a trusted source.
+
+use std::{
+    error, fmt,
+    io::{self, Read, Write},
+};
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    journal::{Journal, Recording},
+    mem::{self, Generation},
+    Frame, Memory, Strategy, Trail, TrailBuilder,
+};
+
+/// The magic bytes at the start of every checkpoint.
+const MAGIC: &[u8; 8] = b"CONTRAIL";
+
+/// The version of the checkpoint format written by this version of the crate.
+pub const VERSION: u32 = 4;
+
+#[cfg(target_endian = "little")]
+const ENDIANNESS: u8 = 0;
+#[cfg(target_endian = "big")]
+const ENDIANNESS: u8 = 1;
+
+const POINTER_WIDTH: u8 = std::mem::size_of::<usize>() as u8;
+
+/// An error that occurred while loading a checkpoint.
+#[derive(Debug)]
+pub enum LoadError {
+    /// Reading the checkpoint failed.
+    Io(io::Error),
+    /// The checkpoint doesn't start with the magic bytes.
+    NotACheckpoint,
+    /// The checkpoint was written with an unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// The checkpoint was written on a platform with a different endianness or pointer width.
+    PlatformMismatch,
+    /// The checksum doesn't match the contents of the checkpoint.
+    ChecksumMismatch,
+    /// The layout of a memory in the checkpoint doesn't match the `TrailBuilder`.
+    LayoutMismatch {
+        /// The number of bytes added to the `TrailBuilder`.
+        expected: usize,
+        /// The number of bytes in the checkpoint.
+        found: usize,
+    },
+    /// The checkpoint is malformed.
+    Corrupt,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "failed to read checkpoint: {}", err),
+            LoadError::NotACheckpoint => write!(f, "not a checkpoint"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "unsupported checkpoint version {}", version)
+            }
+            LoadError::PlatformMismatch => {
+                write!(f, "checkpoint was written on an incompatible platform")
+            }
+            LoadError::ChecksumMismatch => write!(f, "checkpoint checksum mismatch"),
+            LoadError::LayoutMismatch { expected, found } => write!(
+                f,
+                "checkpoint memory layout has {} bytes but the builder has {} bytes",
+                found, expected
+            ),
+            LoadError::Corrupt => write!(f, "checkpoint is corrupt"),
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl Trail {
+    /// Saves the trail to the given writer.
+    ///
+    /// See the [`checkpoint`](crate::checkpoint) module for the format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, Trail, TrailBuilder};
+    ///
+    /// let new_builder = || {
+    ///     let mut builder = TrailBuilder::new();
+    ///     let value = BacktrackableValue::new(&mut builder, 0);
+    ///     (builder, value)
+    /// };
+    ///
+    /// let (builder, value) = new_builder();
+    /// let mut trail = builder.finish();
+    /// trail.new_level();
+    /// value.set(&mut trail, 1);
+    ///
+    /// let mut checkpoint = vec![];
+    /// trail.save(&mut checkpoint).unwrap();
+    ///
+    /// let (builder, value) = new_builder();
+    /// let mut trail = Trail::load(builder, &checkpoint[..]).unwrap();
+    /// assert_eq!(value.get(&trail), 1);
+    ///
+    /// trail.backtrack();
+    /// assert_eq!(value.get(&trail), 0);
+    /// ```
+    pub fn save(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut out = Encoder::default();
+        out.bytes(MAGIC);
+        out.bytes(&VERSION.to_le_bytes());
+        out.u8(ENDIANNESS);
+        out.u8(POINTER_WIDTH);
+        out.u8(match self.strategy {
+            Strategy::Copy => 0,
+            Strategy::Log => 1,
+            Strategy::Paged => 2,
+            Strategy::Adaptive => 3,
+            Strategy::Stamped => 4,
+        });
+        out.usize(self.last_level_written);
+        out.u64(self.next_level_id);
+
+        out.memory(&self.backtrackable_mem);
+        out.memory(&self.non_backtrackable_mem);
+        out.memory(&self.restart_mem);
+
+        out.usize(self.trail.len());
+        for frame in &self.trail {
+            out.u64(frame.id);
+            out.usize(frame.mark);
+            out.usize(frame.len);
+            out.u8(match frame.recording {
+                Recording::Off => 0,
+                Recording::Writes => 1,
+                Recording::Pages => 2,
+                Recording::Words => 3,
+            });
+            out.usize(frame.written);
+            out.usize(frame.resumed);
+            match &frame.snapshot {
+                Some(snapshot) => {
+                    out.u8(1);
+                    out.usize(snapshot.len());
+                    out.bytes(snapshot);
+                }
+                None => out.u8(0),
+            }
+            match &frame.allocations {
+                Some(allocations) => {
+                    out.u8(1);
+                    out.allocations(allocations);
+                }
+                None => out.u8(0),
+            }
+        }
+
+        let checksum = fnv1a(&out.buf);
+        out.u64(checksum);
+        writer.write_all(&out.buf)
+    }
+
+    /// Loads a trail saved with [`save()`](Trail::save) from the given reader.
+    ///
+    /// The `TrailBuilder` must have the same layout as the builder of the saved trail, which
+    /// means the same values and arrays must be added to it in the same order, including any
+    /// values added to the saved trail with [`extend()`](Trail::extend). Every `Value` and
+    /// `Array` created with the `TrailBuilder` is usable with the loaded trail. The strategy of
+    /// the loaded trail is the strategy of the saved trail.
+    ///
+    /// The checkpoint must come from a trusted source, since the bytes of the saved memories
+    /// aren't validated. See [trusted input](crate::checkpoint#trusted-input).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, if the checkpoint is invalid or was written on an
+    /// incompatible platform, or if the layout of the `TrailBuilder` doesn't match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{checkpoint::LoadError, BacktrackableValue, Trail, TrailBuilder};
+    ///
+    /// let mut checkpoint = vec![];
+    /// TrailBuilder::new().finish().save(&mut checkpoint).unwrap();
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// BacktrackableValue::new(&mut builder, 0);
+    ///
+    /// match Trail::load(builder, &checkpoint[..]) {
+    ///     Err(LoadError::LayoutMismatch { expected, found }) => {
+    ///         assert_eq!(expected, 4);
+    ///         assert_eq!(found, 0);
+    ///     }
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn load(builder: TrailBuilder, mut reader: impl Read) -> Result<Trail, LoadError> {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+
+        let (contents, checksum) = buf.split_at(buf.len().saturating_sub(8));
+        let mut input = Decoder { buf: contents };
+        if input.bytes(MAGIC.len())? != MAGIC {
+            return Err(LoadError::NotACheckpoint);
+        }
+        let version = input.u32()?;
+        if version != VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        if input.u8()? != ENDIANNESS || input.u8()? != POINTER_WIDTH {
+            return Err(LoadError::PlatformMismatch);
+        }
+        if fnv1a(contents).to_le_bytes() != checksum {
+            return Err(LoadError::ChecksumMismatch);
+        }
+
+        let strategy = match input.u8()? {
+            0 => Strategy::Copy,
+            1 => Strategy::Log,
+            2 => Strategy::Paged,
+            3 => Strategy::Adaptive,
+            4 => Strategy::Stamped,
+            _ => return Err(LoadError::Corrupt),
+        };
+        let last_level_written = input.usize()?;
+        let next_level_id = input.u64()?;
+
+        let mut trail = builder.finish();
+        trail.strategy = strategy;
+        trail.last_level_written = last_level_written;
+        trail.next_level_id = next_level_id;
+        input.memory(&mut trail.backtrackable_mem)?;
+        input.memory(&mut trail.non_backtrackable_mem)?;
+        input.memory(&mut trail.restart_mem)?;
+
+        let levels = input.usize()?;
+        for _ in 0..levels {
+            let frame = Frame {
+                id: input.u64()?,
+                mark: input.usize()?,
+                len: input.usize()?,
+                recording: match input.u8()? {
+                    0 => Recording::Off,
+                    1 => Recording::Writes,
+                    2 => Recording::Pages,
+                    3 => Recording::Words,
+                    _ => return Err(LoadError::Corrupt),
+                },
+                written: input.usize()?,
+                resumed: input.usize()?,
+                actions: 0,
+                snapshot: match input.u8()? {
+                    0 => None,
+                    1 => {
+                        let len = input.usize()?;
+                        Some(input.bytes(len)?.to_vec())
+                    }
+                    _ => return Err(LoadError::Corrupt),
+                },
+                allocations: match input.u8()? {
+                    0 => None,
+                    1 => Some(input.allocations()?),
+                    _ => return Err(LoadError::Corrupt),
+                },
+            };
+
+            if !trail.push_loaded_frame(frame) {
+                return Err(LoadError::Corrupt);
+            }
+        }
+        if !input.buf.is_empty() || !trail.finish_loading() {
+            return Err(LoadError::Corrupt);
+        }
+        Ok(trail)
+    }
+}
+
+impl Trail {
+    /// Adds a level read from a saved trail to the trail, or returns `false` if the level isn't
+    /// consistent with the memory and the levels below it.
+    fn push_loaded_frame(&mut self, frame: Frame) -> bool {
+        // the levels must describe a stack of states that the memory can be restored to. Restoring
+        // a snapshot can shrink the memory, so a level can be shorter than its parent, or longer
+        // than the memory; see `finish_loading` for the latter
+        let memory = &self.backtrackable_mem;
+        let (min_id, min_mark) = self
+            .trail
+            .last()
+            .map_or((0, 0), |parent| (parent.id + 1, parent.mark));
+        let valid = (min_id..self.next_level_id).contains(&frame.id)
+            && (min_mark..=memory.journal().mark()).contains(&frame.mark)
+            && frame.len >= memory.layout_len()
+            && frame.snapshot.iter().all(|s| s.len() == frame.len)
+            && (frame.snapshot.is_some() == (frame.recording == Recording::Off))
+            && frame
+                .allocations
+                .iter()
+                .all(|allocations| mem::is_allocation_list(allocations));
+        if valid {
+            self.trail.push(frame);
+            // the policies of user-defined storage modes start at the root, so they need to see
+            // the level being added to restore their memories when it's removed
+            self.regions.new_level(self.trail.len());
+        }
+        valid
+    }
+
+    /// Prepares a trail whose memories and levels were just loaded for use, or returns `false` if
+    /// the backtrackable memory can't hold what undoing its journal and removing its levels
+    /// brings back.
+    fn finish_loading(&mut self) -> bool {
+        // every byte past the end of the memory that backtracking brings back was saved either in
+        // the journal or in a level's copy of the memory, which bounds how long the memory can
+        // have been
+        let memory = &self.backtrackable_mem;
+        let limit = self
+            .trail
+            .iter()
+            .filter_map(|frame| frame.snapshot.as_ref().map(Vec::len))
+            .fold(memory.len().max(memory.journal().saved_len()), usize::max);
+        let needed = self
+            .trail
+            .iter()
+            .flat_map(|frame| {
+                let allocations = frame.allocations.as_deref().unwrap_or(&[]);
+                allocations
+                    .iter()
+                    .map(|&(end, _)| end)
+                    .chain(Some(frame.len))
+            })
+            .fold(memory.journal().end(), usize::max);
+        if needed > limit {
+            return false;
+        }
+        self.backtrackable_mem.reserve(needed);
+        self.update_recording();
+        true
+    }
+}
+
+/// The serialized form of `Trail`.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[serde(rename = "Trail")]
+struct SerializeTrail<'a> {
+    strategy: Strategy,
+    last_level_written: usize,
+    next_level_id: u64,
+    backtrackable_mem: &'a Memory,
+    non_backtrackable_mem: &'a Memory,
+    constant_mem: &'a Memory,
+    restart_mem: &'a Memory,
+    initial_restart_mem: &'a Memory,
+    levels: &'a [Frame],
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Trail")]
+struct DeserializeTrail {
+    strategy: Strategy,
+    last_level_written: usize,
+    next_level_id: u64,
+    backtrackable_mem: Memory,
+    non_backtrackable_mem: Memory,
+    constant_mem: Memory,
+    restart_mem: Memory,
+    initial_restart_mem: Memory,
+    levels: Vec<Frame>,
+}
+
+/// Serializes everything that [`save()`](Trail::save) saves.
+#[cfg(feature = "serde")]
+impl Serialize for Trail {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeTrail {
+            strategy: self.strategy,
+            last_level_written: self.last_level_written,
+            next_level_id: self.next_level_id,
+            backtrackable_mem: &self.backtrackable_mem,
+            non_backtrackable_mem: &self.non_backtrackable_mem,
+            constant_mem: &self.constant_mem,
+            restart_mem: &self.restart_mem,
+            initial_restart_mem: &self.initial_restart_mem,
+            levels: &self.trail,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Trail {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let trail = DeserializeTrail::deserialize(deserializer)?;
+        let mut result = Trail {
+            id: crate::new_trail_id(),
+            backtrackable_mem: trail.backtrackable_mem,
+            non_backtrackable_mem: trail.non_backtrackable_mem,
+            strategy: trail.strategy,
+            trail: vec![],
+            last_level_written: trail.last_level_written,
+            next_level_id: trail.next_level_id,
+            last_snapshot: vec![],
+            layout: Default::default(),
+            stats: Default::default(),
+            backtrack_actions: vec![],
+            watchers: Default::default(),
+            regions: Default::default(),
+            constant_mem: std::sync::Arc::new(trail.constant_mem),
+            restart_mem: trail.restart_mem,
+            initial_restart_mem: trail.initial_restart_mem,
+        };
+        for frame in trail.levels {
+            if !result.push_loaded_frame(frame) {
+                return Err(de::Error::custom("inconsistent trail level"));
+            }
+        }
+        if !result.finish_loading() {
+            return Err(de::Error::custom("undo log entry out of bounds"));
+        }
+        Ok(result)
+    }
+}
+
+/// Computes the 64-bit FNV-1a hash of the given bytes.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    fn u64(&mut self, val: u64) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    fn usize(&mut self, val: usize) {
+        self.u64(val as u64);
+    }
+
+    fn memory(&mut self, memory: &Memory) {
+        self.usize(memory.layout_len());
+        self.usize(memory.len());
+        self.bytes(memory.as_slice());
+        self.usize(memory.bytes_written());
+        let (entries, saved) = memory.journal().parts();
+        let entries = entries.collect::<Vec<_>>();
+        self.usize(entries.len());
+        for (offset, len) in entries {
+            self.usize(offset);
+            self.usize(len);
+        }
+        self.bytes(saved);
+        self.allocations(memory.allocations());
+    }
+
+    fn allocations(&mut self, allocations: &[(usize, Generation)]) {
+        self.usize(allocations.len());
+        for &(end, generation) in allocations {
+            self.usize(end);
+            self.u64(generation.to_u64());
+        }
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        if len > self.buf.len() {
+            return Err(LoadError::Corrupt);
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn usize(&mut self) -> Result<usize, LoadError> {
+        let val = self.u64()?;
+        if val > usize::MAX as u64 {
+            return Err(LoadError::Corrupt);
+        }
+        Ok(val as usize)
+    }
+
+    /// Reads a memory into `memory`, which was just created by the `TrailBuilder`.
+    fn memory(&mut self, memory: &mut Memory) -> Result<(), LoadError> {
+        let layout_len = self.usize()?;
+        if layout_len != memory.len() {
+            return Err(LoadError::LayoutMismatch {
+                expected: memory.len(),
+                found: layout_len,
+            });
+        }
+        let len = self.usize()?;
+        if len < layout_len {
+            return Err(LoadError::Corrupt);
+        }
+        let bytes = self.bytes(len)?.to_vec();
+        let written = self.usize()?;
+
+        let count = self.usize()?;
+        let mut entries = Vec::with_capacity(count.min(self.buf.len() / 16));
+        for _ in 0..count {
+            let offset = self.usize()?;
+            let entry_len = self.usize()?;
+            entries.push((offset, entry_len));
+        }
+        let saved_len = entries
+            .iter()
+            .try_fold(0usize, |total, &(_, len)| total.checked_add(len))
+            .ok_or(LoadError::Corrupt)?;
+        let saved = self.bytes(saved_len)?.to_vec();
+        let journal = Journal::from_parts(entries, saved).ok_or(LoadError::Corrupt)?;
+
+        let allocations = self.allocations()?;
+        if allocations.iter().any(|&(end, _)| end > len) {
+            return Err(LoadError::Corrupt);
+        }
+        memory.set_state(bytes, allocations, journal, written);
+        Ok(())
+    }
+
+    fn allocations(&mut self) -> Result<Vec<(usize, Generation)>, LoadError> {
+        let count = self.usize()?;
+        let mut allocations = Vec::with_capacity(count.min(self.buf.len() / 16));
+        for _ in 0..count {
+            let end = self.usize()?;
+            let generation = Generation::from_u64(self.u64()?).ok_or(LoadError::Corrupt)?;
+            allocations.push((end, generation));
+        }
+        if !mem::is_allocation_list(&allocations) {
+            return Err(LoadError::Corrupt);
+        }
+        Ok(allocations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::{StorageMode, Undo},
+        BacktrackableArray, BacktrackableValue, NonBacktrackableValue, RestartValue, Value,
+    };
+
+    fn new_builder() -> (
+        TrailBuilder,
+        BacktrackableArray<u32>,
+        NonBacktrackableValue<u8>,
+    ) {
+        let mut builder = TrailBuilder::with_strategy(Strategy::Adaptive);
+        let array = BacktrackableArray::new(&mut builder, 0..100);
+        let value = NonBacktrackableValue::new(&mut builder, 0);
+        (builder, array, value)
+    }
+
+    #[test]
+    fn save_load_levels() {
+        for &strategy in &crate::tests::all_strategies() {
+            let (builder, array, value) = new_builder();
+            let mut trail = builder.finish();
+            trail.strategy = strategy;
+
+            let first = trail.new_level();
+            array.set(&mut trail, 0, 1000);
+            trail.new_level();
+            array.set(&mut trail, 99, 1000);
+            value.set(&mut trail, 7);
+
+            let mut checkpoint = vec![];
+            trail.save(&mut checkpoint).unwrap();
+
+            let (builder, array, value) = new_builder();
+            let mut trail = Trail::load(builder, &checkpoint[..]).unwrap();
+            assert_eq!(trail.strategy(), strategy);
+            assert!(trail.contains_level(first));
+            assert_eq!(value.get(&trail), 7);
+            assert_eq!(array.get(&trail, 99), 1000);
+
+            trail.backtrack();
+            assert_eq!(array.get(&trail, 99), 99);
+            assert_eq!(array.get(&trail, 0), 1000);
+
+            trail.backtrack_to(first);
+            assert_eq!(array.get(&trail, 0), 0);
+            assert_eq!(value.get(&trail), 7);
+        }
+    }
+
+    #[test]
+    fn save_load_allocations() {
+        for &strategy in &crate::tests::all_strategies() {
+            let (builder, _, _) = new_builder();
+            let mut trail = builder.finish();
+            trail.strategy = strategy;
+
+            // allocated at the root, so it lives as long as the trail
+            BacktrackableValue::alloc(&mut trail, 5u32);
+            let snapshot = trail.snapshot();
+            trail.new_level();
+            BacktrackableArray::alloc(&mut trail, vec![6u64; 40]);
+            trail.new_level();
+            trail.restore(&snapshot);
+            trail.new_level();
+
+            let mut checkpoint = vec![];
+            trail.save(&mut checkpoint).unwrap();
+
+            let (builder, _, _) = new_builder();
+            let mut loaded = Trail::load(builder, &checkpoint[..]).unwrap();
+            for _ in 0..4 {
+                assert_eq!(
+                    loaded.backtrackable_mem.as_slice(),
+                    trail.backtrackable_mem.as_slice()
+                );
+                assert_eq!(
+                    loaded.backtrackable_mem.allocations(),
+                    trail.backtrackable_mem.allocations()
+                );
+                loaded.backtrack();
+                trail.backtrack();
+            }
+            assert_eq!(loaded.backtrackable_mem.allocations().len(), 1);
+        }
+    }
+
+    #[test]
+    fn save_load_restart() {
+        let new_builder = || {
+            let mut builder = TrailBuilder::new();
+            let counter = RestartValue::new(&mut builder, 10);
+            (builder, counter)
+        };
+        let (builder, counter) = new_builder();
+        let mut trail = builder.finish();
+        trail.new_level();
+        counter.set(&mut trail, 11);
+
+        let mut checkpoint = vec![];
+        trail.save(&mut checkpoint).unwrap();
+
+        let (builder, counter) = new_builder();
+        let mut trail = Trail::load(builder, &checkpoint[..]).unwrap();
+        assert_eq!(counter.get(&trail), 11);
+
+        trail.backtrack();
+        assert_eq!(counter.get(&trail), 11);
+
+        trail.restart();
+        assert_eq!(counter.get(&trail), 10);
+    }
+
+    #[test]
+    fn save_load_regions() {
+        #[derive(Clone, Copy, Debug, Default)]
+        struct Logged;
+
+        impl StorageMode for Logged {
+            const NAME: &'static str = "Logged";
+            type Policy = Undo;
+        }
+
+        let new_builder = || {
+            let mut builder = TrailBuilder::new();
+            let logged = Value::<Logged, u32>::new(&mut builder, 1);
+            (builder, logged)
+        };
+        let (builder, _) = new_builder();
+        let mut trail = builder.finish();
+        trail.new_level();
+        trail.new_level();
+
+        let mut checkpoint = vec![];
+        trail.save(&mut checkpoint).unwrap();
+
+        // the memory isn't saved, but writes at loaded levels are undone
+        let (builder, logged) = new_builder();
+        let mut trail = Trail::load(builder, &checkpoint[..]).unwrap();
+        logged.set(&mut trail, 2);
+        trail.backtrack();
+        assert_eq!(logged.get(&trail), 1);
+    }
+
+    #[test]
+    fn load_errors() {
+        let (builder, _, _) = new_builder();
+        let mut trail = builder.finish();
+        trail.new_level();
+        let mut checkpoint = vec![];
+        trail.save(&mut checkpoint).unwrap();
+
+        let load = |checkpoint: &[u8]| Trail::load(new_builder().0, checkpoint).err();
+
+        assert!(load(&checkpoint).is_none());
+        assert!(matches!(load(b"CONTRAIL"), Some(LoadError::Corrupt)));
+        assert!(matches!(
+            load(b"not a checkpoint"),
+            Some(LoadError::NotACheckpoint)
+        ));
+
+        let mut bad = checkpoint.clone();
+        bad[8] = 1;
+        assert!(matches!(load(&bad), Some(LoadError::UnsupportedVersion(1))));
+
+        let mut bad = checkpoint.clone();
+        bad[12] ^= 1;
+        assert!(matches!(load(&bad), Some(LoadError::PlatformMismatch)));
+
+        let mut bad = checkpoint.clone();
+        bad[30] ^= 1;
+        assert!(matches!(load(&bad), Some(LoadError::ChecksumMismatch)));
+
+        let mut bad = checkpoint.clone();
+        bad.truncate(bad.len() - 1);
+        assert!(matches!(load(&bad), Some(LoadError::ChecksumMismatch)));
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde {
+        use super::*;
+
+        #[test]
+        fn roundtrip() {
+            let (builder, array, value) = new_builder();
+            let mut trail = builder.finish();
+            trail.new_level();
+            array.set(&mut trail, 0, 1000);
+            value.set(&mut trail, 5);
+            trail.new_level();
+            array.set(&mut trail, 99, 1000);
+
+            let json = serde_json::to_string(&(&trail, array, value)).unwrap();
+            let (mut json_trail, json_array, json_value): (
+                Trail,
+                BacktrackableArray<u32>,
+                NonBacktrackableValue<u8>,
+            ) = serde_json::from_str(&json).unwrap();
+
+            let encoded = bincode::serialize(&trail).unwrap();
+            let mut bincode_trail: Trail = bincode::deserialize(&encoded).unwrap();
+
+            for loaded in [&mut json_trail, &mut bincode_trail] {
+                assert_eq!(loaded.trail_len(), 2);
+                assert_eq!(json_array.get(loaded, 99), 1000);
+                loaded.backtrack();
+                assert_eq!(json_array.get(loaded, 0), 1000);
+                assert_eq!(json_array.get(loaded, 99), 99);
+                loaded.backtrack();
+                assert_eq!(json_array.get(loaded, 0), 0);
+                assert_eq!(json_value.get(loaded), 5);
+            }
+        }
+
+        #[test]
+        fn alloc() {
+            let mut trail = TrailBuilder::new().finish();
+            let root = BacktrackableValue::alloc(&mut trail, 1u32);
+            trail.new_level();
+            let scratch = BacktrackableValue::alloc(&mut trail, 2u32);
+
+            let json = serde_json::to_string(&(&trail, root, scratch)).unwrap();
+            let (mut loaded, root, scratch): (
+                Trail,
+                BacktrackableValue<u32>,
+                BacktrackableValue<u32>,
+            ) = serde_json::from_str(&json).unwrap();
+            assert_eq!(root.get(&loaded), 1);
+            assert_eq!(scratch.get(&loaded), 2);
+
+            // allocations after loading get new generations
+            loaded.backtrack();
+            let reused = BacktrackableValue::alloc(&mut loaded, 3u32);
+            assert_eq!(reused.byte_range(), scratch.byte_range());
+            assert_eq!(root.get(&loaded), 1);
+            assert_eq!(reused.get(&loaded), 3);
+        }
+
+        #[test]
+        #[should_panic(expected = "pointer used after the memory it points to was freed")]
+        fn alloc_freed() {
+            let mut trail = TrailBuilder::new().finish();
+            trail.new_level();
+            let scratch = BacktrackableValue::alloc(&mut trail, 2u32);
+
+            let json = serde_json::to_string(&(&trail, scratch)).unwrap();
+            let (mut loaded, scratch): (Trail, BacktrackableValue<u32>) =
+                serde_json::from_str(&json).unwrap();
+            loaded.backtrack();
+            BacktrackableValue::alloc(&mut loaded, 3u32);
+            scratch.get(&loaded);
+        }
+
+        #[test]
+        fn constant() {
+            let mut builder = TrailBuilder::new();
+            let table = crate::ConstantArray::new(&mut builder, vec![7u16, 8, 9]);
+            let trail = builder.finish();
+
+            let json = serde_json::to_string(&(&trail, table)).unwrap();
+            let (loaded, table): (Trail, crate::ConstantArray<u16>) =
+                serde_json::from_str(&json).unwrap();
+            assert_eq!(table.iter(&loaded).collect::<Vec<_>>(), vec![7, 8, 9]);
+        }
+
+        #[test]
+        fn invalid() {
+            let (builder, _, _) = new_builder();
+            let mut trail = builder.finish();
+            trail.new_level();
+            let json = serde_json::to_value(&trail).unwrap();
+
+            let mut bad = json.clone();
+            bad["levels"][0]["mark"] = serde_json::json!(1000);
+            assert!(serde_json::from_value::<Trail>(bad).is_err());
+
+            let mut bad = json.clone();
+            bad["backtrackable_mem"]["entries"] = serde_json::json!([[0, 1]]);
+            assert!(serde_json::from_value::<Trail>(bad).is_err());
+
+            let mut bad = json.clone();
+            bad["backtrackable_mem"]["allocations"] = serde_json::json!([[1000, 1]]);
+            assert!(serde_json::from_value::<Trail>(bad).is_err());
+        }
+    }
+}
@@ -0,0 +1,429 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public License,
+ * v. 2.0. If a copy of the MPL was not distributed with this file, You can
+ * obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Undo logs for memory.
+
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The size in bytes of a page of memory when recording pages.
+pub(crate) const PAGE_SIZE: usize = 256;
+
+/// The size in bytes of a word of memory when recording words.
+pub(crate) const WORD_SIZE: usize = 8;
+
+/// What a journal records when memory is written.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) enum Recording {
+    /// Nothing is recorded.
+    #[default]
+    Off,
+    /// The overwritten bytes of every write are recorded.
+    Writes,
+    /// The page containing the overwritten bytes is recorded on the first write to the page since
+    /// the dirty pages were last cleared.
+    Pages,
+    /// The word containing the overwritten bytes is recorded on the first write to the word since
+    /// the dirty pages were last cleared. Each word is stamped with the epoch of its last
+    /// recording, so clearing every word only takes a new epoch.
+    Words,
+}
+
+/// A record of overwritten bytes that can be replayed in reverse to undo writes to memory.
+///
+/// Each entry stores the offset and length of a write, and the bytes that were overwritten are
+/// stored contiguously in a single buffer to avoid an allocation per entry.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Journal {
+    recording: Recording,
+    entries: Vec<Entry>,
+    saved: Vec<u8>,
+    /// Whether each page has been recorded since the dirty pages were last cleared.
+    dirty: Vec<bool>,
+    /// The indices of the pages marked as dirty.
+    dirty_pages: Vec<usize>,
+    /// The epoch in which each word was last recorded. A word is dirty if its stamp is the
+    /// current epoch, and new words are stamped `u32::MAX`, which is never the current epoch.
+    stamps: Vec<u32>,
+    /// The current epoch, which changes whenever the dirty pages are cleared.
+    epoch: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Entry {
+    offset: usize,
+    len: usize,
+}
+
+impl Journal {
+    /// Checks if writes are currently being recorded.
+    #[inline]
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recording != Recording::Off
+    }
+
+    /// Sets what is recorded when memory is written.
+    pub(crate) fn set_recording(&mut self, recording: Recording) {
+        self.recording = recording;
+    }
+
+    /// Marks every page and word as clean, so that the next write to each page or word records
+    /// it again.
+    pub(crate) fn clear_dirty(&mut self) {
+        for page in self.dirty_pages.drain(..) {
+            self.dirty[page] = false;
+        }
+        if self.epoch == u32::MAX - 1 {
+            // the epochs have wrapped around, so old stamps have to be erased
+            self.stamps.iter_mut().for_each(|stamp| *stamp = u32::MAX);
+            self.epoch = 0;
+        } else {
+            self.epoch += 1;
+        }
+    }
+
+    /// Returns the number of entries in the journal.
+    ///
+    /// The returned value can later be passed to [`undo`](Journal::undo) or
+    /// [`truncate`](Journal::truncate).
+    pub(crate) fn mark(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Records the bytes in `offset..(offset + len)` before they are overwritten.
+    #[inline]
+    pub(crate) fn record(&mut self, bytes: &[u8], offset: usize, len: usize) {
+        match self.recording {
+            Recording::Off => {}
+            Recording::Writes => self.push(bytes, offset, len),
+            Recording::Pages => {
+                if len > 0 {
+                    for page in (offset / PAGE_SIZE)..=((offset + len - 1) / PAGE_SIZE) {
+                        self.record_page(bytes, page);
+                    }
+                }
+            }
+            Recording::Words => {
+                if len > 0 {
+                    self.record_words(bytes, offset / WORD_SIZE, (offset + len - 1) / WORD_SIZE);
+                }
+            }
+        }
+    }
+
+    /// Records the words in `first..=last` that aren't dirty yet, with one entry for each run of
+    /// consecutive clean words.
+    fn record_words(&mut self, bytes: &[u8], first: usize, last: usize) {
+        if last >= self.stamps.len() {
+            self.stamps.resize(bytes.len() / WORD_SIZE + 1, u32::MAX);
+        }
+        let mut word = first;
+        while word <= last {
+            if self.stamps[word] == self.epoch {
+                word += 1;
+                continue;
+            }
+            let start = word;
+            while word <= last && self.stamps[word] != self.epoch {
+                self.stamps[word] = self.epoch;
+                word += 1;
+            }
+            let offset = start * WORD_SIZE;
+            self.push(bytes, offset, (word * WORD_SIZE).min(bytes.len()) - offset);
+        }
+    }
+
+    /// Records an entire page unless it's already dirty.
+    fn record_page(&mut self, bytes: &[u8], page: usize) {
+        if page >= self.dirty.len() {
+            self.dirty.resize(bytes.len() / PAGE_SIZE + 1, false);
+        }
+        if !self.dirty[page] {
+            self.dirty[page] = true;
+            self.dirty_pages.push(page);
+            let offset = page * PAGE_SIZE;
+            self.push(bytes, offset, PAGE_SIZE.min(bytes.len() - offset));
+        }
+    }
+
+    /// Records a snapshot of the entire memory, so that undoing the entry restores the snapshot.
+    ///
+    /// The snapshot must be the same length as the memory.
+    pub(crate) fn record_snapshot(&mut self, snapshot: &[u8]) {
+        self.push(snapshot, 0, snapshot.len());
+    }
+
+    fn push(&mut self, bytes: &[u8], offset: usize, len: usize) {
+        self.entries.push(Entry { offset, len });
+        self.saved.extend_from_slice(&bytes[offset..(offset + len)]);
+    }
+
+    /// Undoes every write recorded after the given mark, most recent first.
+    pub(crate) fn undo(&mut self, bytes: &mut [u8], mark: usize) {
+        while self.entries.len() > mark {
+            let entry = self.entries.pop().unwrap();
+            let start = self.saved.len() - entry.len;
+            bytes[entry.offset..(entry.offset + entry.len)].copy_from_slice(&self.saved[start..]);
+            self.saved.truncate(start);
+        }
+    }
+
+    /// Undoes the writes recorded between the given marks on a copy of the bytes in
+    /// `offset..(offset + bytes.len())`, most recent first, without changing the journal.
+    pub(crate) fn undo_window(&self, bytes: &mut [u8], offset: usize, marks: Range<usize>) {
+        let mut end = self.saved.len()
+            - self.entries[marks.end..]
+                .iter()
+                .map(|entry| entry.len)
+                .sum::<usize>();
+        for entry in self.entries[marks].iter().rev() {
+            let start = end - entry.len;
+            // the overlap of the entry with the window
+            let lo = entry.offset.max(offset);
+            let hi = (entry.offset + entry.len).min(offset + bytes.len());
+            if lo < hi {
+                bytes[(lo - offset)..(hi - offset)].copy_from_slice(
+                    &self.saved[(start + lo - entry.offset)..(start + hi - entry.offset)],
+                );
+            }
+            end = start;
+        }
+    }
+
+    /// Returns the offset and length of every entry, oldest first, along with the overwritten
+    /// bytes of every entry stored contiguously.
+    pub(crate) fn parts(&self) -> (impl Iterator<Item = (usize, usize)> + '_, &[u8]) {
+        let entries = self.entries.iter().map(|entry| (entry.offset, entry.len));
+        (entries, &self.saved)
+    }
+
+    /// Creates a journal from the parts returned by [`parts`](Journal::parts), or returns `None`
+    /// if the lengths of the entries don't add up to the length of the overwritten bytes or an
+    /// entry ends past `usize::MAX`.
+    ///
+    /// The entries aren't checked against the memory, since it may have been longer when they
+    /// were recorded. See [`end`](Journal::end).
+    pub(crate) fn from_parts(entries: Vec<(usize, usize)>, saved: Vec<u8>) -> Option<Self> {
+        let total = entries
+            .iter()
+            .try_fold(0usize, |total, &(_, len)| total.checked_add(len))?;
+        if total != saved.len()
+            || entries
+                .iter()
+                .any(|&(offset, len)| offset.checked_add(len).is_none())
+        {
+            return None;
+        }
+        Some(Self {
+            entries: entries
+                .into_iter()
+                .map(|(offset, len)| Entry { offset, len })
+                .collect(),
+            saved,
+            ..Self::default()
+        })
+    }
+
+    /// Returns the offset just past the last byte of the entry that ends the furthest, or 0 if
+    /// there are no entries.
+    pub(crate) fn end(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.offset + entry.len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of overwritten bytes stored for every entry.
+    pub(crate) fn saved_len(&self) -> usize {
+        self.saved.len()
+    }
+
+    /// Returns the number of bytes used by the entries and the overwritten bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.entries.len() * std::mem::size_of::<Entry>() + self.saved.len()
+    }
+
+    /// Discards every entry recorded after the given mark without undoing it.
+    pub(crate) fn truncate(&mut self, mark: usize) {
+        let discarded = self.entries[mark..]
+            .iter()
+            .map(|entry| entry.len)
+            .sum::<usize>();
+        self.entries.truncate(mark);
+        self.saved.truncate(self.saved.len() - discarded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_undo() {
+        let mut bytes = vec![0, 1, 2, 3, 4, 5];
+        let mut journal = Journal::default();
+        journal.set_recording(Recording::Writes);
+
+        let mark = journal.mark();
+        journal.record(&bytes, 1, 2);
+        bytes[1] = 10;
+        bytes[2] = 20;
+        journal.record(&bytes, 2, 3);
+        bytes[2] = 30;
+        bytes[3] = 40;
+        bytes[4] = 50;
+
+        assert_eq!(bytes, vec![0, 10, 30, 40, 50, 5]);
+
+        journal.undo(&mut bytes, mark);
+
+        assert_eq!(bytes, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(journal.mark(), mark);
+    }
+
+    #[test]
+    fn truncate() {
+        let mut bytes = vec![0, 1, 2, 3];
+        let mut journal = Journal::default();
+        journal.set_recording(Recording::Writes);
+
+        journal.record(&bytes, 0, 1);
+        bytes[0] = 10;
+
+        let mark = journal.mark();
+        journal.record(&bytes, 1, 2);
+        bytes[1] = 11;
+        journal.truncate(mark);
+
+        assert_eq!(journal.mark(), 1);
+
+        journal.undo(&mut bytes, 0);
+
+        assert_eq!(bytes, vec![0, 11, 2, 3]);
+    }
+
+    #[test]
+    fn record_pages() {
+        let mut bytes = vec![0; 2 * PAGE_SIZE + 10];
+        let mut journal = Journal::default();
+        journal.set_recording(Recording::Pages);
+
+        // the first write to a page records the whole page
+        journal.record(&bytes, 3, 1);
+        bytes[3] = 1;
+        assert_eq!(journal.mark(), 1);
+
+        // later writes to the same page are free
+        journal.record(&bytes, 4, 4);
+        bytes[4] = 1;
+        assert_eq!(journal.mark(), 1);
+
+        // a write spanning two pages records both, including the partial last page
+        journal.record(&bytes, 2 * PAGE_SIZE - 1, 2);
+        bytes[2 * PAGE_SIZE - 1] = 1;
+        bytes[2 * PAGE_SIZE] = 1;
+        assert_eq!(journal.mark(), 3);
+
+        // once the dirty pages are cleared, pages are recorded again
+        journal.clear_dirty();
+        journal.record(&bytes, 3, 1);
+        bytes[3] = 2;
+        assert_eq!(journal.mark(), 4);
+
+        journal.undo(&mut bytes, 1);
+        assert_eq!(bytes[3], 1);
+        assert_eq!(bytes[2 * PAGE_SIZE], 0);
+
+        journal.undo(&mut bytes, 0);
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn record_words() {
+        let mut bytes = vec![0; 4 * WORD_SIZE + 3];
+        let mut journal = Journal::default();
+        journal.set_recording(Recording::Words);
+
+        // the first write to a word records the whole word
+        journal.record(&bytes, 1, 2);
+        bytes[1] = 1;
+        assert_eq!(journal.mark(), 1);
+
+        // later writes to the same word are free
+        journal.record(&bytes, 0, 8);
+        bytes[7] = 1;
+        assert_eq!(journal.mark(), 1);
+
+        // a write spanning a dirty word and two clean words records the clean words together,
+        // including the partial last word
+        journal.record(&bytes, WORD_SIZE - 1, 3 * WORD_SIZE + 4);
+        bytes[WORD_SIZE..].iter_mut().for_each(|byte| *byte = 1);
+        assert_eq!(journal.mark(), 2);
+        journal.record(&bytes, 3 * WORD_SIZE, WORD_SIZE + 3);
+        assert_eq!(journal.mark(), 2);
+
+        // once the dirty words are cleared, words are recorded again
+        journal.clear_dirty();
+        journal.record(&bytes, 1, 1);
+        bytes[1] = 2;
+        assert_eq!(journal.mark(), 3);
+
+        journal.undo(&mut bytes, 2);
+        assert_eq!(bytes[1], 1);
+
+        journal.undo(&mut bytes, 0);
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn epoch_wraps() {
+        let bytes = vec![0; WORD_SIZE];
+        let mut journal = Journal::default();
+        journal.set_recording(Recording::Words);
+
+        journal.epoch = u32::MAX - 2;
+        journal.record(&bytes, 0, 1);
+        journal.clear_dirty();
+        journal.clear_dirty();
+        assert_eq!(journal.epoch, 0);
+
+        // the stamp from before the wrap doesn't make the word dirty
+        journal.record(&bytes, 0, 1);
+        assert_eq!(journal.mark(), 2);
+    }
+
+    #[test]
+    fn undo_window() {
+        let mut bytes = vec![0, 1, 2, 3, 4, 5];
+        let mut journal = Journal::default();
+        journal.set_recording(Recording::Writes);
+
+        journal.record(&bytes, 1, 3);
+        bytes[1..4].copy_from_slice(&[10, 20, 30]);
+        journal.record(&bytes, 3, 2);
+        bytes[3..5].copy_from_slice(&[40, 50]);
+        journal.record(&bytes, 0, 1);
+        bytes[0] = 60;
+
+        // the window sees the state before the first two writes, but after the third
+        let mut window = bytes[2..5].to_vec();
+        journal.undo_window(&mut window, 2, 0..2);
+        assert_eq!(window, vec![2, 3, 4]);
+
+        let mut window = bytes[3..4].to_vec();
+        journal.undo_window(&mut window, 3, 1..2);
+        assert_eq!(window, vec![30]);
+
+        // the journal is unchanged
+        assert_eq!(journal.mark(), 3);
+        journal.undo(&mut bytes, 0);
+        assert_eq!(bytes, vec![0, 1, 2, 3, 4, 5]);
+    }
+}
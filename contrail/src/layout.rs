@@ -0,0 +1,283 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public License,
+ * v. 2.0. If a copy of the MPL was not distributed with this file, You can
+ * obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Names and types of values stored on the trail.
+//!
+//! Values and arrays created with [`Value::named`](crate::Value::named) and
+//! [`Array::named`](crate::Array::named) are recorded in the trail's [`Layout`](Layout), which
+//! can be used to find where a value is stored or to print the current contents of every named
+//! value with [`Trail::inspect`](crate::Trail::inspect).
+
+use std::{fmt, ops::Range, slice};
+
+use crate::{
+    mem::{Bytes, Memory},
+    storage::Readable,
+    Trail,
+};
+
+/// The names, types and locations of the named values stored on a trail.
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{BacktrackableArray, NonBacktrackableValue, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let xs = BacktrackableArray::named(&mut builder, "xs", vec![1u32, 2, 3]);
+/// let count = NonBacktrackableValue::named(&mut builder, "count", 0u64);
+/// let trail = builder.finish();
+///
+/// let entry = trail.layout().get("xs").unwrap();
+/// assert_eq!(entry.type_name(), "u32");
+/// assert_eq!(entry.array_len(), Some(3));
+/// assert_eq!(entry.byte_range(), xs.byte_range());
+///
+/// let names = trail.layout().iter().map(|entry| entry.name()).collect::<Vec<_>>();
+/// assert_eq!(names, vec!["xs", "count"]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    entries: Vec<LayoutEntry>,
+}
+
+impl Layout {
+    /// Returns the entry with the given name, or `None` if no value has that name.
+    pub fn get(&self, name: &str) -> Option<&LayoutEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Returns the number of named values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks if there are no named values.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns an iterator over the entries in the order the values were created.
+    pub fn iter(&self) -> slice::Iter<'_, LayoutEntry> {
+        self.entries.iter()
+    }
+
+    /// Records a named value or array with elements of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value with the same name was already recorded.
+    pub(crate) fn push<M, T>(
+        &mut self,
+        name: String,
+        region: usize,
+        offset: usize,
+        len: Option<usize>,
+    ) where
+        M: Readable,
+        T: Bytes + fmt::Debug,
+    {
+        assert!(
+            self.get(&name).is_none(),
+            "a value named `{}` already exists",
+            name
+        );
+        self.entries.push(LayoutEntry {
+            name,
+            storage_mode: M::NAME,
+            type_name: std::any::type_name::<T>(),
+            offset,
+            len,
+            size: T::LENGTH,
+            region,
+            memory: M::memory,
+            fmt_element: fmt_bytes::<T>,
+        });
+    }
+}
+
+impl<'a> IntoIterator for &'a Layout {
+    type Item = &'a LayoutEntry;
+    type IntoIter = slice::Iter<'a, LayoutEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The name, type and location of a single named value or array.
+#[derive(Clone)]
+pub struct LayoutEntry {
+    name: String,
+    storage_mode: &'static str,
+    type_name: &'static str,
+    offset: usize,
+    len: Option<usize>,
+    size: usize,
+    region: usize,
+    memory: fn(&Trail, usize) -> &Memory,
+    fmt_element: fn(&[u8], &mut fmt::Formatter) -> fmt::Result,
+}
+
+impl LayoutEntry {
+    /// Returns the name of the value.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name of the [storage mode](crate::storage) of the value, such as
+    /// `"Backtrackable"`.
+    pub fn storage_mode(&self) -> &'static str {
+        self.storage_mode
+    }
+
+    /// Returns the name of the type of the value, or of the elements if the entry is an array.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Returns the number of elements if the entry is an array, or `None` if it's a single
+    /// value.
+    pub fn array_len(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// Returns the range of bytes occupied by the value in its memory.
+    pub fn byte_range(&self) -> Range<usize> {
+        self.offset..(self.offset + self.size * self.len.unwrap_or(1))
+    }
+
+    fn fmt_contents(&self, trail: &Trail, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = &(self.memory)(trail, self.region).as_slice()[self.byte_range()];
+        match self.len {
+            None => (self.fmt_element)(bytes, f),
+            Some(_) => {
+                f.write_str("[")?;
+                for (i, element) in bytes.chunks(self.size).enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    (self.fmt_element)(element, f)?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+impl fmt::Debug for LayoutEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LayoutEntry")
+            .field("name", &self.name)
+            .field("storage_mode", &self.storage_mode)
+            .field("type_name", &self.type_name)
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// Formats a value of type `T` read from the given bytes.
+fn fmt_bytes<T>(bytes: &[u8], f: &mut fmt::Formatter) -> fmt::Result
+where
+    T: Bytes + fmt::Debug,
+{
+    // safe because the bytes were written by a pointer of type `T`
+    fmt::Debug::fmt(&unsafe { T::read_bytes(bytes) }, f)
+}
+
+/// The current contents of every named value on a trail, one per line.
+///
+/// This struct is created by [`Trail::inspect`](crate::Trail::inspect).
+pub struct Inspect<'t> {
+    pub(crate) trail: &'t Trail,
+}
+
+impl<'t> fmt::Display for Inspect<'t> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.trail.layout() {
+            write!(f, "{}: ", entry.name)?;
+            match entry.len {
+                None => write!(f, "{}", entry.type_name)?,
+                Some(len) => write!(f, "[{}; {}]", entry.type_name, len)?,
+            }
+            f.write_str(" = ")?;
+            entry.fmt_contents(self.trail, f)?;
+            let range = entry.byte_range();
+            writeln!(
+                f,
+                " ({}, bytes {}..{})",
+                entry.storage_mode, range.start, range.end
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<'t> fmt::Debug for Inspect<'t> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BacktrackableArray, BacktrackableValue, NonBacktrackableValue, TrailBuilder};
+
+    #[test]
+    fn inspect() {
+        let mut builder = TrailBuilder::new();
+        let x = BacktrackableValue::named(&mut builder, "x[3]", 5i32);
+        BacktrackableValue::new(&mut builder, 0u8);
+        let xs = BacktrackableArray::named(&mut builder, "xs", vec![true, false]);
+        NonBacktrackableValue::named(&mut builder, "count", 7usize);
+        let mut trail = builder.finish();
+
+        trail.new_level();
+        x.set(&mut trail, -1);
+        xs.set(&mut trail, 1, true);
+
+        let usize_len = std::mem::size_of::<usize>();
+        assert_eq!(
+            trail.inspect().to_string(),
+            format!(
+                "x[3]: i32 = -1 (Backtrackable, bytes 0..4)\n\
+                 xs: [bool; 2] = [true, true] (Backtrackable, bytes 5..7)\n\
+                 count: usize = 7 (NonBacktrackable, bytes 0..{})\n",
+                usize_len
+            )
+        );
+
+        trail.backtrack();
+        assert!(trail
+            .inspect()
+            .to_string()
+            .starts_with("x[3]: i32 = 5 (Backtrackable, bytes 0..4)\n"));
+    }
+
+    #[test]
+    fn extend() {
+        let mut builder = TrailBuilder::new();
+        BacktrackableValue::named(&mut builder, "a", 1u8);
+        let mut trail = builder.finish();
+
+        let b = trail.extend(|builder| BacktrackableValue::named(builder, "b", 2u8));
+
+        assert_eq!(trail.layout().len(), 2);
+        assert_eq!(
+            trail.layout().get("b").unwrap().byte_range(),
+            b.byte_range()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "a value named `a` already exists")]
+    fn duplicate_name() {
+        let mut builder = TrailBuilder::new();
+        BacktrackableValue::named(&mut builder, "a", 1u8);
+        NonBacktrackableValue::named(&mut builder, "a", 1u8);
+    }
+}
@@ -18,7 +18,11 @@
 //! # Limitations
 //!
 //! The main limitation of contrail is that once a trail has been created using a trail builder,
-//! nothing can be added to the trail. This is to prevent dangling pointers.
+//! values that live as long as the trail can only be added using [`Trail::extend`](Trail::extend)
+//! while the trail has no levels. During search, backtrackable values can be allocated with
+//! [`Value::alloc`](Value::alloc) and [`Array::alloc`](Array::alloc), but they only live until the
+//! level they were allocated at is removed, and using one after that panics. This is to prevent
+//! dangling pointers.
 
 #[allow(unused_imports)]
 #[macro_use]
@@ -26,14 +30,38 @@ extern crate contrail_derive;
 #[doc(hidden)]
 pub use contrail_derive::*;
 
+pub mod branded;
+pub mod checkpoint;
+mod journal;
+pub mod layout;
 pub mod mem;
 pub mod storage;
+pub mod watch;
 
-use std::{fmt, marker::PhantomData};
+use std::{
+    any::{self, Any},
+    collections::{vec_deque, BTreeMap},
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut, Range, RangeBounds},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    mem::{ArrayPointer, Bytes, Memory, MemoryBuilder, Pointer},
-    storage::{Backtrackable, NonBacktrackable, StorageMode},
+    journal::{Recording, PAGE_SIZE},
+    layout::{Inspect, Layout},
+    mem::{ArrayPointer, Bytes, Generation, Memory, MemoryBuilder, Plain, Pointer},
+    storage::{
+        Backtrackable, Constant, NonBacktrackable, Readable, RegionBuilders, Regions, Restart,
+        StorageMode,
+    },
+    watch::{Event, EventKind, Location, WatchId, Watchers},
 };
 
 /// The trail itself.
@@ -42,14 +70,23 @@ use crate::{
 ///
 /// The trail consists of [_backtrackable_](storage::Backtrackable) memory and
 /// [_non-backtrackable_](storage::NonBacktrackable) memory. Both types of storage can be used with
-/// [`Value`](Value) and [`Array`](Array). Whenever `trail.new_level()` is called, a clone of the
-/// backtrackable memory is made and appended to an internal stack.  Conversely, whenever
-/// `trail.backtrack()` is called, the current backtrackable memory is replaced with the most
-/// recent clone from the internal stack.  Non-backtrackable memory is unaffected by these methods.
+/// [`Value`](Value) and [`Array`](Array). Whenever `trail.new_level()` is called, the state of the
+/// backtrackable memory is saved on an internal stack.  Conversely, whenever `trail.backtrack()` is
+/// called, the current backtrackable memory is restored to the most recently saved state.
+/// Non-backtrackable memory is unaffected by these methods.
 ///
 /// When designing data structures using the trail, try to store as much as possible in
 /// non-backtrackable storage. This will make calls to `new_level()` and `backtrack()` more
-/// efficient as less data will need to be cloned.
+/// efficient as less data will need to be saved.
+///
+/// # Strategies
+///
+/// How the backtrackable memory is saved is determined by the trail's [`Strategy`](Strategy),
+/// which is chosen when creating the [`TrailBuilder`](TrailBuilder::with_strategy). The trail can
+/// either make a clone of the entire backtrackable memory for every level, or record the bytes
+/// overwritten by writes in an undo log, which is much cheaper when only a small part of the
+/// backtrackable memory changes between levels. By default, the trail chooses between the two for
+/// each level based on how much memory was written at previous levels.
 ///
 /// # Examples
 ///
@@ -122,17 +159,108 @@ use crate::{
 /// 3
 /// ```
 pub struct Trail {
+    /// An identifier distinct from every other trail's, which changes whenever values are added
+    /// to the trail so that snapshots are only restored to memory with the same layout.
+    id: u64,
     backtrackable_mem: Memory,
     non_backtrackable_mem: Memory,
-    trail: Vec<Memory>,
+    strategy: Strategy,
+    trail: Vec<Frame>,
+    /// The number of bytes written at the most recently removed level.
+    last_level_written: usize,
+    /// The identifier given to the next level added to the trail.
+    next_level_id: u64,
+    /// The pages of the most recent snapshot taken or restored, which later snapshots share when
+    /// a page is unchanged.
+    last_snapshot: Vec<Arc<[u8]>>,
+    /// The named values stored on the trail.
+    layout: Layout,
+    /// The counters that are updated as the trail is used. The level stack size and write
+    /// counts are computed when the statistics are requested.
+    stats: TrailStats,
+    /// The actions to run when the levels they were registered at are removed, oldest first.
+    backtrack_actions: Vec<BacktrackAction<'static>>,
+    /// The watched values and the events they fired.
+    watchers: Watchers,
+    /// The memories of user-defined storage modes.
+    regions: Regions,
+    /// The constant memory, which is shared by forked trails.
+    constant_mem: Arc<Memory>,
+    restart_mem: Memory,
+    /// The contents of the restart memory when the trail was finished, which it's reset to when
+    /// the trail is restarted.
+    initial_restart_mem: Memory,
+}
+
+/// The identifier given to the next trail.
+static NEXT_TRAIL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a trail identifier distinct from every identifier returned before.
+fn new_trail_id() -> u64 {
+    NEXT_TRAIL_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// An action registered with [`Trail::on_backtrack`](Trail::on_backtrack), passed the trail and
+/// the external state given when backtracking.
+pub(crate) type BacktrackAction<'a> = Box<dyn FnOnce(&mut Trail, &mut dyn Any) + Send + Sync + 'a>;
+
+/// Returns the external state passed when backtracking as an `E`.
+///
+/// # Panics
+///
+/// Panics if the external state isn't an `E`.
+fn downcast_ext<E: 'static>(ext: &mut dyn Any) -> &mut E {
+    ext.downcast_mut().unwrap_or_else(|| {
+        panic!(
+            "backtrack action expects external state of type `{}`",
+            any::type_name::<E>()
+        )
+    })
+}
+
+/// The saved state of the backtrackable memory for a single level of the trail.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Frame {
+    /// The identifier of the level, unique within the trail.
+    id: u64,
+    /// The length of the undo log when the level was added.
+    mark: usize,
+    /// The length of the backtrackable memory when the level was added. Anything allocated after
+    /// that is freed when the level is removed.
+    len: usize,
+    /// A clone of the backtrackable memory, or `None` if writes are recorded in the undo log.
+    snapshot: Option<Vec<u8>>,
+    /// The allocations in the backtrackable memory when a snapshot was first restored at this
+    /// level, or `None` if no snapshot was restored. Restoring a snapshot replaces every
+    /// allocation, so removing the level puts these back.
+    allocations: Option<Vec<(usize, Generation)>>,
+    /// What is recorded in the undo log while this level is the most recent level.
+    recording: Recording,
+    /// The number of bytes written while this level was the most recent level, not counting the
+    /// time since `resumed`.
+    written: usize,
+    /// The value of the memory's write counter when this level last became the most recent
+    /// level.
+    resumed: usize,
+    /// The number of backtrack actions when the level was added. Actions registered after that
+    /// are run when the level is removed.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    actions: usize,
 }
 
 impl Trail {
     /// Adds a new level to the trail.
     ///
-    /// When this method is called, a clone of the trail's backtrackable memory at that point in
-    /// time is added to an internal stack of memory. These memory snapshots can be recalled in
-    /// FILO order using [`backtrack()`](Trail::backtrack).
+    /// When this method is called, the state of the trail's backtrackable memory at that point in
+    /// time is saved on an internal stack. These saved states can be recalled in FILO order using
+    /// [`backtrack()`](Trail::backtrack).
+    ///
+    /// Depending on the trail's [`Strategy`](Strategy), the state is saved either by cloning the
+    /// backtrackable memory or by recording every subsequent write in an undo log. The adaptive
+    /// strategy makes this choice for each level individually.
+    ///
+    /// The returned [`Level`](Level) identifies the new level, and can be passed to
+    /// [`backtrack_to()`](Trail::backtrack_to) to remove it later.
     ///
     /// # Examples
     ///
@@ -149,15 +277,51 @@ impl Trail {
     /// trail.backtrack();
     /// assert_eq!(value.get(&trail), 1);
     /// ```
-    pub fn new_level(&mut self) {
-        self.trail.push(self.backtrackable_mem.clone());
+    pub fn new_level(&mut self) -> Level {
+        let written = self.backtrackable_mem.bytes_written();
+        if let Some(parent) = self.trail.last_mut() {
+            parent.written += written.wrapping_sub(parent.resumed);
+        }
+
+        let mark = self.backtrackable_mem.journal_mut().mark();
+        let recording = self.next_level_recording();
+        let snapshot = match recording {
+            Recording::Off => {
+                self.stats.snapshot_bytes += self.backtrackable_mem.len();
+                Some(self.backtrackable_mem.snapshot())
+            }
+            _ => None,
+        };
+        let id = self.next_level_id;
+        self.next_level_id += 1;
+        let len = self.backtrackable_mem.len();
+        self.trail.push(Frame {
+            id,
+            mark,
+            len,
+            snapshot,
+            allocations: None,
+            recording,
+            written: 0,
+            resumed: written,
+            actions: self.backtrack_actions.len(),
+        });
+        self.update_recording();
+        self.regions.new_level(self.trail.len());
+        self.stats.levels_created += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.trail.len());
+
+        Level {
+            depth: self.trail.len(),
+            id,
+        }
     }
 
     /// Backtracks the trail to the most recent level.
     ///
-    /// When this method is called, the most recent backtrackable memory stored in the trail's
-    /// internal stack is removed from the stack and set as the current backtrackable memory. If
-    /// the trail is empty, this method has no effect.
+    /// When this method is called, the most recent state saved on the trail's internal stack is
+    /// removed from the stack and the backtrackable memory is restored to that state. If the trail
+    /// is empty, this method has no effect.
     ///
     /// # Examples
     ///
@@ -175,142 +339,277 @@ impl Trail {
     /// assert_eq!(value.get(&trail), 1);
     /// ```
     pub fn backtrack(&mut self) {
-        if let Some(prev) = self.trail.pop() {
-            self.backtrackable_mem = prev;
+        self.backtrack_with(&mut ());
+    }
+
+    /// Backtracks the trail to the most recent level, passing `ext` to the actions registered
+    /// with [`on_backtrack_with`](Trail::on_backtrack_with).
+    ///
+    /// See [`backtrack`](Trail::backtrack).
+    ///
+    /// # Panics
+    ///
+    /// Panics if an action that runs expects external state of a type other than `E`.
+    pub fn backtrack_with<E: 'static>(&mut self, ext: &mut E) {
+        if let Some(depth) = self.trail.len().checked_sub(1) {
+            self.truncate(depth, ext);
         }
     }
 
-    /// Returns the length of the trail.
+    /// Removes the most recent level from the trail without restoring its state.
     ///
-    /// The length of the trail is increased whenever a level is added, and decreased whenever a
-    /// backtrack occurs.
+    /// Any changes made to the backtrackable memory since the most recent level was added are
+    /// kept, and become part of the parent level: backtracking the parent level undoes them as
+    /// well. If the trail is empty, this method has no effect.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::TrailBuilder;
+    /// use contrail::{BacktrackableValue, TrailBuilder};
     ///
-    /// let mut trail = TrailBuilder::new().finish();
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
     ///
-    /// assert_eq!(trail.trail_len(), 0);
+    /// trail.new_level();
+    /// value.set(&mut trail, 1);
     ///
+    /// // probe a change and keep it
     /// trail.new_level();
+    /// value.set(&mut trail, 2);
+    /// trail.commit_level();
+    ///
+    /// assert_eq!(value.get(&trail), 2);
     /// assert_eq!(trail.trail_len(), 1);
     ///
+    /// // the kept change is undone along with the parent level
     /// trail.backtrack();
+    /// assert_eq!(value.get(&trail), 0);
+    /// ```
+    pub fn commit_level(&mut self) {
+        if let Some(frame) = self.trail.pop() {
+            let written = self.backtrackable_mem.bytes_written();
+            let journal = self.backtrackable_mem.journal_mut();
+            match self.trail.last_mut() {
+                Some(parent) => {
+                    if parent.snapshot.is_some() {
+                        // the parent level restores its own snapshot
+                        journal.truncate(frame.mark);
+                    } else if let Some(snapshot) = frame.snapshot {
+                        // the writes made at the committed level weren't recorded, so record the
+                        // entire memory as it was when the level was added
+                        journal.record_snapshot(&snapshot);
+                    }
+                    parent.written += frame.written + written.wrapping_sub(frame.resumed);
+                    parent.resumed = written;
+                    if parent.allocations.is_none() {
+                        parent.allocations = frame.allocations;
+                    }
+                }
+                // there's nothing left to restore or undo
+                None => {
+                    journal.truncate(frame.mark);
+                    self.backtrack_actions.truncate(frame.actions);
+                }
+            }
+            self.update_recording();
+            self.regions.commit_level(self.trail.len());
+        }
+    }
+
+    /// Backtracks the trail until the given level is removed.
+    ///
+    /// After this method is called, the backtrackable memory is in the same state as when
+    /// `level` was returned by [`new_level()`](Trail::new_level), and the length of the trail is
+    /// one less than it was at that point. This is equivalent to calling
+    /// [`backtrack()`](Trail::backtrack) until `level` is removed, but the states of the
+    /// intermediate levels are never restored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` has already been removed from the trail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    ///
+    /// let level = trail.new_level();
+    /// for i in 1..10 {
+    ///     value.set(&mut trail, i);
+    ///     trail.new_level();
+    /// }
+    ///
+    /// trail.backtrack_to(level);
+    /// assert_eq!(value.get(&trail), 0);
     /// assert_eq!(trail.trail_len(), 0);
     /// ```
-    pub fn trail_len(&self) -> usize {
-        self.trail.len()
+    pub fn backtrack_to(&mut self, level: Level) {
+        self.backtrack_to_with(level, &mut ());
     }
 
-    /// Checks if the trail's length is 0.
+    /// Backtracks the trail to the state before the given level was added, passing `ext` to the
+    /// actions registered with [`on_backtrack_with`](Trail::on_backtrack_with).
+    ///
+    /// See [`backtrack_to`](Trail::backtrack_to).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` has already been removed from the trail, or if an action that runs
+    /// expects external state of a type other than `E`.
+    pub fn backtrack_to_with<E: 'static>(&mut self, level: Level, ext: &mut E) {
+        assert!(
+            self.contains_level(level),
+            "level has already been removed from the trail"
+        );
+        self.truncate(level.depth - 1, ext);
+    }
+
+    /// Backtracks the trail until every level is removed.
+    ///
+    /// After this method is called, the backtrackable memory is in the same state as before the
+    /// first level was added to the trail.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::TrailBuilder;
+    /// use contrail::{BacktrackableValue, TrailBuilder};
     ///
-    /// let mut trail = TrailBuilder::new().finish();
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    ///
+    /// for i in 1..10 {
+    ///     trail.new_level();
+    ///     value.set(&mut trail, i);
+    /// }
     ///
+    /// trail.reset_to_root();
+    /// assert_eq!(value.get(&trail), 0);
     /// assert!(trail.is_trail_empty());
+    /// ```
+    pub fn reset_to_root(&mut self) {
+        self.truncate(0, &mut ());
+    }
+
+    /// Restarts the trail, removing every level and resetting the restart memory.
+    ///
+    /// After this method is called, the backtrackable memory is in the same state as before the
+    /// first level was added to the trail, as with [`reset_to_root()`](Trail::reset_to_root), and
+    /// every value stored in [restart memory](storage::Restart) is reset to its value when the
+    /// `TrailBuilder` was finished. Non-backtrackable memory is unaffected. As with backtracking,
+    /// resetting values doesn't add events for their [watches](watch).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, NonBacktrackableValue, RestartArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let decision = BacktrackableValue::new(&mut builder, 0);
+    /// let phases = RestartArray::new(&mut builder, vec![false; 4]);
+    /// let learned = NonBacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
     ///
     /// trail.new_level();
-    /// assert!(!trail.is_trail_empty());
+    /// decision.set(&mut trail, 2);
+    /// phases.set(&mut trail, 2, true);
+    /// learned.update(&mut trail, |x| x + 1);
     ///
-    /// trail.backtrack();
+    /// trail.restart();
     /// assert!(trail.is_trail_empty());
+    /// assert_eq!(decision.get(&trail), 0);
+    /// assert_eq!(phases.get(&trail, 2), false);
+    /// assert_eq!(learned.get(&trail), 1);
     /// ```
-    pub fn is_trail_empty(&self) -> bool {
-        self.trail.is_empty()
+    pub fn restart(&mut self) {
+        self.truncate(0, &mut ());
+        self.restart_mem
+            .overwrite(Some(self.initial_restart_mem.as_slice()));
+        self.stats.restarts += 1;
     }
-}
 
-/// A builder to create a `Trail`.
-///
-/// # Examples
-///
-/// ```
-/// use contrail::{BacktrackableValue, TrailBuilder};
-///
-/// let mut builder = TrailBuilder::new();
-/// let value = BacktrackableValue::new(&mut builder, 5);
-/// let trail = builder.finish();
-///
-/// assert_eq!(value.get(&trail), 5);
-/// ```
-#[derive(Debug, Default)]
-pub struct TrailBuilder {
-    backtrackable_mem: MemoryBuilder,
-    non_backtrackable_mem: MemoryBuilder,
-}
-
-impl TrailBuilder {
-    /// Creates a new empty `TrailBuilder`.
+    /// Checks if the given level is still on the trail.
     ///
     /// # Examples
     ///
     /// ```
     /// use contrail::TrailBuilder;
     ///
-    /// let mut builder = TrailBuilder::new();
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// let level = trail.new_level();
+    /// assert!(trail.contains_level(level));
+    ///
+    /// trail.backtrack();
+    /// assert!(!trail.contains_level(level));
+    ///
+    /// // a new level at the same depth is a different level
+    /// trail.new_level();
+    /// assert!(!trail.contains_level(level));
     /// ```
-    pub fn new() -> Self {
-        Self {
-            backtrackable_mem: MemoryBuilder::new(),
-            non_backtrackable_mem: MemoryBuilder::new(),
-        }
+    pub fn contains_level(&self, level: Level) -> bool {
+        level
+            .depth
+            .checked_sub(1)
+            .and_then(|index| self.trail.get(index))
+            .map(|frame| frame.id)
+            == Some(level.id)
     }
 
-    /// Consumes the `TrailBuilder` to create a new `Trail`.
+    /// Registers an action to run when the most recent level is removed by backtracking.
     ///
-    /// Once this method is called, any `Value` and `Array` that were created using the
-    /// `TrailBuilder` are usable with the resulting `Trail`.
+    /// When levels are removed, the actions registered at those levels run in the reverse order
+    /// they were registered. Each action is passed the trail after its levels have been removed,
+    /// so it can read the restored values. To keep state that can't be stored on the trail, such
+    /// as a `Vec` or a cache, consistent with the trail, use
+    /// [`on_backtrack_with`](Trail::on_backtrack_with) instead.
+    ///
+    /// If the level is [committed](Trail::commit_level), its actions are kept and run when the
+    /// parent level is removed instead. Actions registered while the trail has no levels, or
+    /// committed to the root, are dropped without running, since there's nothing to backtrack.
+    /// Actions aren't [saved](Trail::save) along with the trail.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{NonBacktrackableArray, NonBacktrackableValue, TrailBuilder};
+    /// use contrail::{BacktrackableValue, NonBacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let value = NonBacktrackableValue::new(&mut builder, -123);
-    /// let array = NonBacktrackableArray::new(&mut builder, vec![1, 3, 5, 7]);
-    /// let trail = builder.finish();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let restored = NonBacktrackableValue::new(&mut builder, -1);
+    /// let mut trail = builder.finish();
     ///
-    /// assert_eq!(value.get(&trail), -123);
-    /// assert_eq!(array.get(&trail, 2), 5);
+    /// trail.new_level();
+    /// value.set(&mut trail, 1);
+    /// trail.on_backtrack(move |trail| restored.set(trail, value.get(trail)));
+    ///
+    /// trail.backtrack();
+    /// assert_eq!(restored.get(&trail), 0);
     /// ```
-    pub fn finish(self) -> Trail {
-        Trail {
-            backtrackable_mem: self.backtrackable_mem.finish(),
-            non_backtrackable_mem: self.non_backtrackable_mem.finish(),
-            trail: vec![],
-        }
+    pub fn on_backtrack(&mut self, action: impl FnOnce(&mut Trail) + Send + Sync + 'static) {
+        self.push_backtrack_action(Box::new(move |trail, _| action(trail)));
     }
-}
-
-/// A reference to a value stored on the trail.
-///
-/// The type parameter `T` is the type of value stored on the trail, and the type parameter `M`
-/// represents how the value is stored on the trail.  A `Value<Backtrackable, T>` is stored on the
-/// trail in backtrackable memory, whereas a `Value<NonBacktrackable, T>` is stored on the trail in
-/// non-backtrackable memory.
-///
-/// Instead of using `Value` directly, it's often easier to use the type definitions
-/// [`BacktrackableValue`](BacktrackableValue) and [`NonBacktrackableValue`](NonBacktrackableValue).
-pub struct Value<M, T> {
-    pointer: Pointer<T>,
-    phantom: PhantomData<M>,
-}
 
-impl<M, T> Value<M, T>
-where
-    M: StorageMode,
-    T: Bytes,
-{
-    /// Creates a new `Value` with the given value.
+    /// Registers an action over external state to run when the most recent level is removed by
+    /// backtracking.
     ///
-    /// The `Value` is usable after the `TrailBuilder` used to create it is finished.
+    /// This keeps state that can't be stored on the trail, such as a `Vec` or a cache, consistent
+    /// with the trail: register an action that undoes each change to the state as it's made, and
+    /// pass the state to [`backtrack_with`](Trail::backtrack_with) or
+    /// [`backtrack_to_with`](Trail::backtrack_to_with). The action is passed the trail after its
+    /// levels have been removed along with the state. Otherwise, it behaves like an action
+    /// registered with [`on_backtrack`](Trail::on_backtrack).
+    ///
+    /// # Panics
+    ///
+    /// The action panics when it runs if the levels are removed without external state of type
+    /// `E`, such as by [`backtrack`](Trail::backtrack), [`probe`](Trail::probe) or
+    /// [`reset_to_root`](Trail::reset_to_root).
     ///
     /// # Examples
     ///
@@ -318,384 +617,3256 @@ where
     /// use contrail::{BacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let value = BacktrackableValue::new(&mut builder, 'b');
-    /// let trail = builder.finish();
+    /// let len = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    /// let mut stack: Vec<i32> = vec![];
     ///
-    /// // the value is usable now
-    /// assert_eq!(value.get(&trail), 'b');
+    /// trail.new_level();
+    /// for i in 0..3 {
+    ///     stack.push(i);
+    ///     len.update(&mut trail, |len| len + 1);
+    ///     trail.on_backtrack_with(move |trail, stack: &mut Vec<i32>| {
+    ///         // the level has already been removed
+    ///         assert_eq!(len.get(trail), 0);
+    ///         assert_eq!(stack.pop(), Some(i));
+    ///     });
+    /// }
+    ///
+    /// assert_eq!(stack, vec![0, 1, 2]);
+    /// trail.backtrack_with(&mut stack);
+    /// assert!(stack.is_empty());
     /// ```
-    pub fn new(builder: &mut TrailBuilder, val: T) -> Self {
-        Self {
-            pointer: Pointer::new(M::builder_mut(builder), val),
-            phantom: PhantomData,
+    pub fn on_backtrack_with<E: 'static>(
+        &mut self,
+        action: impl FnOnce(&mut Trail, &mut E) + Send + Sync + 'static,
+    ) {
+        self.push_backtrack_action(Box::new(move |trail, ext| action(trail, downcast_ext(ext))));
+    }
+
+    /// Registers an action to run when the most recent level is removed by backtracking.
+    fn push_backtrack_action(&mut self, action: BacktrackAction<'static>) {
+        if !self.trail.is_empty() {
+            self.backtrack_actions.push(action);
         }
     }
 
-    /// Gets the value from the trail.
+    /// Stops the given watch, so that it no longer adds events. Events that the watch already
+    /// added stay in the queue.
+    ///
+    /// Returns `false` if the watch was already stopped.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{BacktrackableValue, TrailBuilder};
+    /// use contrail::{watch::EventKind, NonBacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let value = NonBacktrackableValue::new(&mut builder, 0);
     /// let mut trail = builder.finish();
     ///
-    /// assert_eq!(value.get(&trail), 5);
+    /// let watch = value.watch(&mut trail, EventKind::Write);
+    /// assert!(trail.unwatch(watch));
+    /// assert!(!trail.unwatch(watch));
+    ///
+    /// value.set(&mut trail, 1);
+    /// assert_eq!(trail.next_event(), None);
     /// ```
-    #[inline]
-    pub fn get(self, trail: &Trail) -> T {
-        self.pointer.get(M::memory(trail))
+    pub fn unwatch(&mut self, watch: WatchId) -> bool {
+        self.watchers.unwatch(watch)
     }
 
-    /// Sets the value on the trail.
+    /// Removes the oldest event from the queue of events fired by [watches](watch), or returns
+    /// `None` if the queue is empty.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{BacktrackableValue, TrailBuilder};
+    /// use contrail::{watch::EventKind, BacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let x = BacktrackableValue::new(&mut builder, 0);
+    /// let y = BacktrackableValue::new(&mut builder, 0);
     /// let mut trail = builder.finish();
     ///
-    /// value.set(&mut trail, 42);
-    /// assert_eq!(value.get(&trail), 42);
+    /// let watch_x = x.watch(&mut trail, EventKind::Change);
+    /// let watch_y = y.watch(&mut trail, EventKind::Write);
+    ///
+    /// y.set(&mut trail, 0);
+    /// x.set(&mut trail, 1);
+    ///
+    /// assert_eq!(trail.next_event().unwrap().watch, watch_y);
+    /// assert_eq!(trail.next_event().unwrap().watch, watch_x);
+    /// assert_eq!(trail.next_event(), None);
     /// ```
-    #[inline]
-    pub fn set(self, trail: &mut Trail, new_val: T) {
-        self.pointer.set(M::memory_mut(trail), new_val);
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.watchers.queue_mut().pop_front()
     }
 
-    /// Updates the value on the trail using the given function.
+    /// Removes every event from the queue of events fired by [watches](watch), returning an
+    /// iterator over the removed events, oldest first.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{BacktrackableValue, TrailBuilder};
+    /// use contrail::{watch::EventKind, BacktrackableArray, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let array = BacktrackableArray::new(&mut builder, vec![0; 4]);
     /// let mut trail = builder.finish();
     ///
-    /// value.update(&mut trail, |x| x * x);
-    /// assert_eq!(value.get(&trail), 25);
+    /// let watches = (0..4)
+    ///     .map(|i| array.watch(&mut trail, i, EventKind::Write))
+    ///     .collect::<Vec<_>>();
+    ///
+    /// array.swap(&mut trail, 3, 1);
+    ///
+    /// let fired = trail.drain_events().map(|event| event.watch).collect::<Vec<_>>();
+    /// assert_eq!(fired, vec![watches[3], watches[1]]);
     /// ```
-    #[inline]
-    pub fn update(self, trail: &mut Trail, f: impl FnOnce(T) -> T) {
-        self.pointer.update(M::memory_mut(trail), f);
+    pub fn drain_events(&mut self) -> vec_deque::Drain<'_, Event> {
+        self.watchers.queue_mut().drain(..)
     }
-}
 
-impl<M, T> Clone for Value<M, T> {
-    fn clone(&self) -> Self {
-        Self {
-            pointer: self.pointer,
-            phantom: PhantomData,
+    /// Writes to the memory of the storage mode `M` in the given region using `write`, which
+    /// must only write the `len` bytes at the given location, and adds events for the watches of
+    /// the value at that location.
+    #[inline]
+    fn write<M>(
+        &mut self,
+        region: usize,
+        location: Location,
+        len: usize,
+        write: impl FnOnce(&mut Memory),
+    ) where
+        M: StorageMode,
+    {
+        if self.watchers.is_empty() || !self.watchers.is_watched(location) {
+            write(M::memory_mut(self, region));
+            return;
+        }
+        let offset = location.offset();
+        let range = offset..offset.wrapping_add(len);
+        let before = M::memory(self, region)
+            .as_slice()
+            .get(range.clone())
+            .map(<[u8]>::to_vec);
+        write(M::memory_mut(self, region));
+        if let Some(before) = before {
+            let changed = M::memory(self, region).as_slice()[range] != before[..];
+            self.watchers.notify(location, changed);
         }
     }
-}
 
-impl<M, T> Copy for Value<M, T> {}
+    /// Writes to the memory of the storage mode `M` in the given region using `write`, which
+    /// must only write the `len` bytes at the given location, and adds events for the watches of
+    /// each value of `size` bytes in that range.
+    fn write_elements<M>(
+        &mut self,
+        region: usize,
+        location: Location,
+        len: usize,
+        size: usize,
+        write: impl FnOnce(&mut Memory),
+    ) where
+        M: StorageMode,
+    {
+        if self.watchers.is_empty() || size == 0 {
+            write(M::memory_mut(self, region));
+            return;
+        }
+        let offset = location.offset();
+        let range = offset..offset.wrapping_add(len);
+        let before = M::memory(self, region)
+            .as_slice()
+            .get(range.clone())
+            .map(<[u8]>::to_vec);
+        write(M::memory_mut(self, region));
+        if let Some(before) = before {
+            for start in (0..before.len()).step_by(size) {
+                let location = location.add(start);
+                if self.watchers.is_watched(location) {
+                    let after =
+                        &M::memory(self, region).as_slice()[(range.start + start)..][..size];
+                    let changed = *after != before[start..(start + size)];
+                    self.watchers.notify(location, changed);
+                }
+            }
+        }
+    }
 
-impl<M, T> fmt::Debug for Value<M, T>
-where
-    M: StorageMode,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Value")
-            .field("pointer", &self.pointer)
-            .field("storage_mode", &M::default())
-            .finish()
+    /// Adds a new level to the trail that is removed when the returned guard is dropped.
+    ///
+    /// The guard dereferences to the trail, so it can be used anywhere a `&Trail` or `&mut Trail`
+    /// is expected. When the guard is dropped, including during a panic, the trail backtracks
+    /// until the level is removed, unless the level was already removed or the guard was
+    /// [committed](LevelGuard::commit).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    ///
+    /// {
+    ///     let mut guard = trail.level_guard();
+    ///     value.set(&mut guard, 1);
+    ///     assert_eq!(value.get(&guard), 1);
+    /// }
+    ///
+    /// assert_eq!(value.get(&trail), 0);
+    /// assert!(trail.is_trail_empty());
+    /// ```
+    pub fn level_guard(&mut self) -> LevelGuard<'_> {
+        let level = self.new_level();
+        LevelGuard { trail: self, level }
     }
-}
 
-impl<M, T> Eq for Value<M, T> {}
+    /// Runs the given function at a new level, then backtracks until the level is removed.
+    ///
+    /// This is useful to check the consequences of a change without keeping it. The trail
+    /// backtracks even if the function panics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 3);
+    /// let mut trail = builder.finish();
+    ///
+    /// let squared = trail.probe(|trail| {
+    ///     value.update(trail, |x| x * x);
+    ///     value.get(trail)
+    /// });
+    ///
+    /// assert_eq!(squared, 9);
+    /// assert_eq!(value.get(&trail), 3);
+    /// ```
+    pub fn probe<R>(&mut self, f: impl FnOnce(&mut Trail) -> R) -> R {
+        let mut guard = self.level_guard();
+        f(&mut guard)
+    }
 
-impl<M, T> PartialEq for Value<M, T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.pointer == other.pointer
+    /// Runs the given function at a new level, then commits the level if the function succeeds
+    /// or backtracks until the level is removed if it fails.
+    ///
+    /// If the function returns `Ok`, its changes are kept as if by
+    /// [`commit_level()`](Trail::commit_level). If it returns `Err` or panics, its changes are
+    /// undone.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 10);
+    /// let mut trail = builder.finish();
+    ///
+    /// let decrement = |trail: &mut contrail::Trail| {
+    ///     value.update(trail, |x| x - 6);
+    ///     if value.get(trail) < 0 {
+    ///         Err("negative")
+    ///     } else {
+    ///         Ok(value.get(trail))
+    ///     }
+    /// };
+    ///
+    /// assert_eq!(trail.transaction(decrement), Ok(4));
+    /// assert_eq!(value.get(&trail), 4);
+    ///
+    /// assert_eq!(trail.transaction(decrement), Err("negative"));
+    /// assert_eq!(value.get(&trail), 4);
+    /// ```
+    pub fn transaction<R, E>(
+        &mut self,
+        f: impl FnOnce(&mut Trail) -> Result<R, E>,
+    ) -> Result<R, E> {
+        let mut guard = self.level_guard();
+        let result = f(&mut guard);
+        if result.is_ok() {
+            guard.commit();
+        }
+        result
     }
-}
 
-/// A reference to a fixed-length array of values stored on the trail.
-///
-/// The type parameter `T` is the type of value stored on the trail, and the type parameter `M`
-/// represents how the value is stored on the trail.  An `Array<Backtrackable, T>` is stored on the
-/// trail in backtrackable memory, whereas an `Array<NonBacktrackable, T>` is stored on the trail
-/// in non-backtrackable memory.
-///
-/// Instead of using `Array` directly, it's often easier to use the type definitions
-/// [`BacktrackableArray`](BacktrackableArray) and
-/// [`NonBacktrackableArray`](NonBacktrackableArray).
-pub struct Array<M, T> {
-    pointer: ArrayPointer<T>,
-    phantom: PhantomData<M>,
-}
+    /// Returns the ranges of bytes in backtrackable memory that have changed since the given level
+    /// was added.
+    ///
+    /// The ranges are sorted and disjoint, and only include bytes whose contents differ from
+    /// when the level was added: a byte that was overwritten with its original value isn't
+    /// included. Bytes allocated since the level was added are always included, and so are bytes
+    /// that were in use when the level was added but no longer are because a smaller
+    /// [`Snapshot`](Snapshot) was restored. Use
+    /// [`Value::byte_range()`](Value::byte_range) and [`Array::byte_range()`](Array::byte_range)
+    /// to find which values overlap the ranges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` has already been removed from the trail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0u32);
+    /// let array = BacktrackableArray::new(&mut builder, vec![0u8; 10]);
+    /// let mut trail = builder.finish();
+    ///
+    /// let level = trail.new_level();
+    /// value.set(&mut trail, 1);
+    /// array.set(&mut trail, 5, 1);
+    /// array.set(&mut trail, 6, 1);
+    /// array.set(&mut trail, 6, 0);
+    ///
+    /// let changed = trail.changed_since(level);
+    /// assert_eq!(changed, vec![0..1, 9..10]);
+    ///
+    /// let overlaps = |range: std::ops::Range<usize>| {
+    ///     changed.iter().any(|c| c.start < range.end && range.start < c.end)
+    /// };
+    /// assert!(overlaps(value.byte_range()));
+    /// assert!(overlaps(array.byte_range()));
+    /// ```
+    pub fn changed_since(&self, level: Level) -> Vec<Range<usize>> {
+        assert!(
+            self.contains_level(level),
+            "level has already been removed from the trail"
+        );
+        let len = self.trail[level.depth - 1].len;
+        let before = self.bytes_at_level(level, 0, len, None);
+        let after = self.backtrackable_mem.as_slice();
 
-impl<M, T> Array<M, T>
-where
-    M: StorageMode,
-    T: Bytes,
-{
-    /// Creates a new `Array` with the given values.
+        // restoring a snapshot can leave the memory shorter than it was at the level, and the
+        // bytes past the end of the shorter of the two have always changed
+        let common = len.min(after.len());
+        let end = len.max(after.len());
+
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for i in (0..common).filter(|&i| before[i] != after[i]) {
+            match ranges.last_mut() {
+                Some(range) if range.end == i => range.end += 1,
+                _ => ranges.push(i..(i + 1)),
+            }
+        }
+        if end > common {
+            match ranges.last_mut() {
+                Some(range) if range.end == common => range.end = end,
+                _ => ranges.push(common..end),
+            }
+        }
+        ranges
+    }
+
+    /// Returns the names, types and locations of the values created with
+    /// [`Value::named`](Value::named) and [`Array::named`](Array::named).
     ///
-    /// The `Array` is usable after the `TrailBuilder` used to create it is finished.
+    /// See the [`layout`](layout) module for an example.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Returns a pretty-printer that shows the current contents of every named value on the
+    /// trail, one per line.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{BacktrackableArray, TrailBuilder};
+    /// use contrail::{BacktrackableArray, NonBacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let array = BacktrackableArray::new(&mut builder, 5..10);
-    /// let trail = builder.finish();
+    /// let xs = BacktrackableArray::named(&mut builder, "xs", vec![1u8, 2, 3]);
+    /// let count = NonBacktrackableValue::named(&mut builder, "count", 0u16);
+    /// let mut trail = builder.finish();
     ///
-    /// // the array is usable now
-    /// assert_eq!(array.get(&trail, 2), 7);
+    /// xs.set(&mut trail, 1, 20);
+    /// count.set(&mut trail, 1);
+    ///
+    /// assert_eq!(
+    ///     trail.inspect().to_string(),
+    ///     "xs: [u8; 3] = [1, 20, 3] (Backtrackable, bytes 0..3)\n\
+    ///      count: u16 = 1 (NonBacktrackable, bytes 0..2)\n",
+    /// );
     /// ```
-    pub fn new(builder: &mut TrailBuilder, vals: impl IntoIterator<Item = T>) -> Self {
-        Self {
-            pointer: ArrayPointer::new(
-                M::builder_mut(builder),
-                &vals.into_iter().collect::<Vec<_>>(),
+    pub fn inspect(&self) -> Inspect<'_> {
+        Inspect { trail: self }
+    }
+
+    /// Returns statistics about how the trail has been used since it was created or since
+    /// [`reset_stats()`](Trail::reset_stats) was last called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, NonBacktrackableValue, Strategy, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::with_strategy(Strategy::Copy);
+    /// let value = BacktrackableValue::new(&mut builder, 0u64);
+    /// let counter = NonBacktrackableValue::new(&mut builder, 0u64);
+    /// let mut trail = builder.finish();
+    ///
+    /// for i in 0..3 {
+    ///     trail.new_level();
+    ///     value.set(&mut trail, i);
+    ///     counter.update(&mut trail, |x| x + 1);
+    /// }
+    /// trail.reset_to_root();
+    ///
+    /// let stats = trail.stats();
+    /// assert_eq!(stats.levels_created, 3);
+    /// assert_eq!(stats.backtracks, 1);
+    /// assert_eq!(stats.max_depth, 3);
+    /// assert_eq!(stats.snapshot_bytes, 3 * 8);
+    /// assert_eq!(stats.level_stack_bytes, 0);
+    /// assert_eq!(stats.writes["Backtrackable"], 3);
+    /// assert_eq!(stats.writes["NonBacktrackable"], 3);
+    /// assert_eq!(stats.writes["Restart"], 0);
+    /// ```
+    pub fn stats(&self) -> TrailStats {
+        let level_stack_bytes = self
+            .trail
+            .iter()
+            .filter_map(|frame| frame.snapshot.as_ref())
+            .map(|snapshot| snapshot.len())
+            .sum::<usize>()
+            + self.backtrackable_mem.journal().size()
+            + self
+                .regions
+                .memories()
+                .map(|(_, memory)| memory.journal().size())
+                .sum::<usize>();
+        let memories = [
+            (
+                <Backtrackable as StorageMode>::NAME,
+                &self.backtrackable_mem,
             ),
-            phantom: PhantomData,
+            (
+                <NonBacktrackable as StorageMode>::NAME,
+                &self.non_backtrackable_mem,
+            ),
+            (<Restart as StorageMode>::NAME, &self.restart_mem),
+        ];
+        let mut writes = BTreeMap::new();
+        for (name, memory) in memories.iter().cloned().chain(self.regions.memories()) {
+            *writes.entry(name).or_insert(0) += memory.writes();
+        }
+        TrailStats {
+            max_depth: self.stats.max_depth.max(self.trail.len()),
+            level_stack_bytes,
+            writes,
+            ..self.stats
         }
     }
 
-    /// Returns the length of the array.
+    /// Resets the statistics returned by [`stats()`](Trail::stats).
+    ///
+    /// Every counter is set to zero, except for the maximum depth, which is set to the current
+    /// length of the trail. The size of the level stack is unaffected, since it isn't a counter.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{BacktrackableArray, TrailBuilder};
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// trail.new_level();
+    /// trail.new_level();
+    /// trail.backtrack();
+    /// trail.reset_stats();
+    ///
+    /// let stats = trail.stats();
+    /// assert_eq!(stats.levels_created, 0);
+    /// assert_eq!(stats.backtracks, 0);
+    /// assert_eq!(stats.max_depth, 1);
+    /// ```
+    pub fn reset_stats(&mut self) {
+        self.stats = TrailStats::default();
+        self.backtrackable_mem.reset_writes();
+        self.non_backtrackable_mem.reset_writes();
+        self.restart_mem.reset_writes();
+        self.regions.reset_writes();
+    }
+
+    /// Returns the length of the trail.
+    ///
+    /// The length of the trail is increased whenever a level is added, and decreased whenever a
+    /// backtrack occurs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// assert_eq!(trail.trail_len(), 0);
+    ///
+    /// trail.new_level();
+    /// assert_eq!(trail.trail_len(), 1);
+    ///
+    /// trail.backtrack();
+    /// assert_eq!(trail.trail_len(), 0);
+    /// ```
+    pub fn trail_len(&self) -> usize {
+        self.trail.len()
+    }
+
+    /// Checks if the trail's length is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// assert!(trail.is_trail_empty());
+    ///
+    /// trail.new_level();
+    /// assert!(!trail.is_trail_empty());
+    ///
+    /// trail.backtrack();
+    /// assert!(trail.is_trail_empty());
+    /// ```
+    pub fn is_trail_empty(&self) -> bool {
+        self.trail.is_empty()
+    }
+
+    /// Returns the strategy used to save the backtrackable memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{Strategy, TrailBuilder};
+    ///
+    /// let trail = TrailBuilder::with_strategy(Strategy::Log).finish();
+    ///
+    /// assert_eq!(trail.strategy(), Strategy::Log);
+    /// ```
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Saves the current state of the backtrackable memory, so that it can be restored later with
+    /// [`restore()`](Trail::restore).
+    ///
+    /// Unlike levels, snapshots are independent of each other and can be restored in any order,
+    /// which allows search strategies other than depth-first search. The memory is saved in
+    /// pages, and pages that are unchanged since the previous snapshot was taken or restored are
+    /// shared with it rather than copied.
+    ///
+    /// Non-backtrackable memory isn't saved.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let array = BacktrackableArray::new(&mut builder, 0..8);
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
     ///
-    /// assert_eq!(array.len(), 8);
+    /// let mut open = vec![];
+    /// for i in 0..3 {
+    ///     value.set(&mut trail, i);
+    ///     open.push(trail.snapshot());
+    /// }
+    ///
+    /// trail.restore(&open[1]);
+    /// assert_eq!(value.get(&trail), 1);
+    ///
+    /// trail.restore(&open[0]);
+    /// assert_eq!(value.get(&trail), 0);
     /// ```
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.pointer.len()
+    pub fn snapshot(&mut self) -> Snapshot {
+        let bytes = self.backtrackable_mem.as_slice();
+        let (last_snapshot, stats) = (&self.last_snapshot, &mut self.stats);
+        let pages = bytes
+            .chunks(PAGE_SIZE)
+            .enumerate()
+            .map(|(i, page)| match last_snapshot.get(i) {
+                Some(last) if **last == *page => Arc::clone(last),
+                _ => {
+                    stats.snapshot_bytes += page.len();
+                    Arc::from(page)
+                }
+            })
+            .collect::<Vec<_>>();
+        self.last_snapshot = pages.clone();
+        Snapshot {
+            trail_id: self.id,
+            pages,
+            allocations: self.backtrackable_mem.allocations().into(),
+        }
     }
 
-    /// Checks if the length of the array is equal to 0.
+    /// Restores the backtrackable memory to the state saved in the given snapshot.
+    ///
+    /// Restoring a snapshot counts as writing to the entire backtrackable memory, so if the trail
+    /// has any levels, backtracking undoes it like any other write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the snapshot was taken from a different trail, including a
+    /// [fork](Trail::fork) of the trail, or if values were added to the trail with
+    /// [`extend()`](Trail::extend) since the snapshot was taken.
     ///
     /// # Examples
     ///
     /// ```
-    /// use contrail::{BacktrackableArray, TrailBuilder};
+    /// use contrail::{BacktrackableValue, TrailBuilder};
     ///
     /// let mut builder = TrailBuilder::new();
-    /// let empty = BacktrackableArray::new(&mut builder, 0..0);
-    /// let not_empty = BacktrackableArray::new(&mut builder, 0..1);
+    /// let value = BacktrackableValue::new(&mut builder, 1);
+    /// let mut trail = builder.finish();
     ///
-    /// assert_eq!(empty.is_empty(), true);
-    /// assert_eq!(not_empty.is_empty(), false);
+    /// let snapshot = trail.snapshot();
+    ///
+    /// trail.new_level();
+    /// value.set(&mut trail, 2);
+    /// trail.restore(&snapshot);
+    /// assert_eq!(value.get(&trail), 1);
+    ///
+    /// trail.backtrack();
+    /// assert_eq!(value.get(&trail), 1);
     /// ```
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.pointer.len() == 0
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        assert!(
+            snapshot.trail_id == self.id,
+            "snapshot restored to a trail other than the trail it was taken from"
+        );
+        if let Some(top) = self.trail.last_mut() {
+            if top.allocations.is_none() {
+                top.allocations = Some(self.backtrackable_mem.allocations().to_vec());
+            }
+        }
+        self.backtrackable_mem
+            .overwrite(snapshot.pages.iter().map(|page| &page[..]));
+        self.backtrackable_mem
+            .set_allocations(snapshot.allocations.to_vec());
+        self.last_snapshot = snapshot.pages.clone();
+    }
+
+    /// Adds new values to the trail using the given function.
+    ///
+    /// The function receives a `TrailBuilder` for the trail, and every `Value` and `Array` it
+    /// creates is usable with the trail once the function returns, alongside the values that
+    /// were already on the trail.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the trail has any levels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, NonBacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 1);
+    /// let mut trail = builder.finish();
+    ///
+    /// let array = trail.extend(|builder| NonBacktrackableArray::new(builder, vec![2, 3]));
+    ///
+    /// assert_eq!(value.get(&trail), 1);
+    /// assert_eq!(array.get(&trail, 1), 3);
+    /// ```
+    pub fn extend<R>(&mut self, f: impl FnOnce(&mut TrailBuilder) -> R) -> R {
+        assert!(
+            self.is_trail_empty(),
+            "values can only be added to a trail with no levels"
+        );
+
+        // adds the new values to the trail even if `f` panics
+        struct Extension<'t> {
+            trail: &'t mut Trail,
+            builder: TrailBuilder,
+        }
+
+        impl<'t> Drop for Extension<'t> {
+            fn drop(&mut self) {
+                self.trail
+                    .backtrackable_mem
+                    .append(&self.builder.backtrackable_mem);
+                self.trail
+                    .non_backtrackable_mem
+                    .append(&self.builder.non_backtrackable_mem);
+                self.trail.layout = std::mem::take(&mut self.builder.layout);
+                self.trail
+                    .regions
+                    .append(std::mem::take(&mut self.builder.regions));
+                Arc::make_mut(&mut self.trail.constant_mem).append(&self.builder.constant_mem);
+                self.trail.restart_mem.append(&self.builder.restart_mem);
+                self.trail
+                    .initial_restart_mem
+                    .append(&self.builder.restart_mem);
+                self.trail.id = new_trail_id();
+            }
+        }
+
+        let builder = TrailBuilder {
+            backtrackable_mem: self.backtrackable_mem.to_builder(),
+            non_backtrackable_mem: self.non_backtrackable_mem.to_builder(),
+            strategy: self.strategy,
+            layout: std::mem::take(&mut self.layout),
+            regions: self.regions.to_builders(),
+            constant_mem: self.constant_mem.to_builder(),
+            restart_mem: self.restart_mem.to_builder(),
+        };
+        let mut extension = Extension {
+            trail: self,
+            builder,
+        };
+        f(&mut extension.builder)
     }
 
-    /// Returns an iterator over the elements of the array.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use contrail::{BacktrackableArray, TrailBuilder};
-    ///
-    /// let mut builder = TrailBuilder::new();
-    /// let odds = BacktrackableArray::new(&mut builder, (0..10).map(|x| 2 * x + 1));
-    /// let trail = builder.finish();
-    ///
-    /// for odd in odds.iter(&trail) {
-    ///     assert_eq!(odd % 2, 1);
-    /// }
-    /// ```
-    pub fn iter<'t>(&self, trail: &'t Trail) -> ArrayIter<'t, M, T> {
-        ArrayIter {
-            trail,
-            index: 0,
-            array: *self,
+    /// Creates a copy of the trail that shares its constant memory.
+    ///
+    /// The copy has the same values, layout and statistics, and every `Value` and `Array` usable
+    /// with the trail is usable with the copy. Watches and the queue of events are copied as
+    /// well. Values stored in [constant memory](storage::Constant) aren't copied, so forking a
+    /// trail is cheap when most of its data is constant, such as when each thread of a parallel
+    /// search works on its own fork of the same model. Values added to either trail with
+    /// [`extend()`](Trail::extend) aren't added to the other, and a [snapshot](Trail::snapshot)
+    /// of either trail can't be restored to the other.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the trail has any levels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, ConstantArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let table = ConstantArray::new(&mut builder, 0..1000);
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    ///
+    /// let mut fork = trail.fork();
+    /// let entry = table.get(&fork, 7);
+    /// value.set(&mut fork, entry);
+    ///
+    /// assert_eq!(value.get(&trail), 0);
+    /// assert_eq!(value.get(&fork), 7);
+    /// ```
+    pub fn fork(&self) -> Trail {
+        assert!(
+            self.is_trail_empty(),
+            "only a trail with no levels can be forked"
+        );
+        Trail {
+            id: new_trail_id(),
+            backtrackable_mem: self.backtrackable_mem.clone(),
+            non_backtrackable_mem: self.non_backtrackable_mem.clone(),
+            strategy: self.strategy,
+            trail: vec![],
+            last_level_written: self.last_level_written,
+            next_level_id: self.next_level_id,
+            last_snapshot: self.last_snapshot.clone(),
+            layout: self.layout.clone(),
+            stats: self.stats.clone(),
+            backtrack_actions: vec![],
+            watchers: self.watchers.clone(),
+            regions: self.regions.fork(),
+            constant_mem: Arc::clone(&self.constant_mem),
+            restart_mem: self.restart_mem.clone(),
+            initial_restart_mem: self.initial_restart_mem.clone(),
+        }
+    }
+
+    /// Removes levels from the trail until its length is `depth`, restoring the backtrackable
+    /// memory to its state when the level at index `depth` was added.
+    fn truncate(&mut self, depth: usize, ext: &mut dyn Any) {
+        let top = match self.trail.last() {
+            Some(top) if depth < self.trail.len() => top,
+            _ => return,
+        };
+        self.stats.backtracks += 1;
+        let written = self.backtrackable_mem.bytes_written();
+        self.last_level_written = top.written + written.wrapping_sub(top.resumed);
+
+        // the lowest snapshot at or above the target level restores the memory to the state when
+        // that snapshot's level was added, and the undo log takes care of the rest
+        let Frame {
+            mark, len, actions, ..
+        } = self.trail[depth];
+        let allocations = self.trail[depth..]
+            .iter_mut()
+            .find_map(|frame| frame.allocations.take());
+        if let Some(index) = self.trail[depth..]
+            .iter()
+            .position(|frame| frame.snapshot.is_some())
+        {
+            let frame = self.trail.swap_remove(depth + index);
+            self.backtrackable_mem
+                .restore(frame.snapshot.unwrap(), frame.mark);
+        }
+        self.backtrackable_mem.undo(mark);
+        self.backtrackable_mem.set_len(len);
+        if let Some(allocations) = allocations {
+            self.backtrackable_mem.set_allocations(allocations);
+        }
+        self.trail.truncate(depth);
+
+        if let Some(parent) = self.trail.last_mut() {
+            parent.resumed = self.backtrackable_mem.bytes_written();
+        }
+        self.update_recording();
+        self.regions.backtrack(depth);
+
+        // the trail is consistent again before any action runs, in case an action panics
+        for action in self.backtrack_actions.split_off(actions).into_iter().rev() {
+            action(self, ext);
+        }
+    }
+
+    /// Returns a copy of the backtrackable memory in the given range as it was when the given
+    /// level was added, checking that the allocation with the given generation, if any, hadn't
+    /// been freed at that time.
+    fn bytes_at_level(
+        &self,
+        level: Level,
+        offset: usize,
+        len: usize,
+        generation: Option<Generation>,
+    ) -> Vec<u8> {
+        assert!(
+            self.contains_level(level),
+            "level has already been removed from the trail"
+        );
+        let depth = level.depth - 1;
+        let frame = &self.trail[depth];
+        assert!(
+            offset + len <= frame.len,
+            "value was allocated after the level was added"
+        );
+        if let Some(generation) = generation {
+            // the allocations below the level's length only change when a snapshot is restored
+            let allocations = self.trail[depth..]
+                .iter()
+                .find_map(|frame| frame.allocations.as_deref())
+                .unwrap_or_else(|| self.backtrackable_mem.allocations());
+            assert!(
+                mem::has_allocation(allocations, generation),
+                "pointer used after the memory it points to was freed"
+            );
+        }
+
+        // as when backtracking, start from the lowest snapshot at or above the level if there is
+        // one, then undo the rest of the way
+        let snapshot = self.trail[depth..].iter().find_map(|frame| {
+            frame
+                .snapshot
+                .as_ref()
+                .map(|snapshot| (&snapshot[..], frame.mark))
+        });
+        self.backtrackable_mem
+            .bytes_at(offset, len, frame.mark, snapshot)
+    }
+
+    /// Returns what to record in the undo log at the next level, where `Recording::Off` means
+    /// that the backtrackable memory is cloned instead.
+    fn next_level_recording(&self) -> Recording {
+        match self.strategy {
+            Strategy::Copy => Recording::Off,
+            Strategy::Log => Recording::Writes,
+            Strategy::Paged => Recording::Pages,
+            Strategy::Stamped => Recording::Words,
+            Strategy::Adaptive => {
+                if self.last_level_written.saturating_mul(ADAPTIVE_LOG_COST)
+                    > self.backtrackable_mem.len()
+                {
+                    Recording::Off
+                } else {
+                    Recording::Writes
+                }
+            }
+        }
+    }
+
+    /// Records writes to backtrackable memory as required by the most recent level. Writes made at
+    /// the root level never need to be undone.
+    ///
+    /// Every page and word becomes clean whenever the most recent level changes, since a page
+    /// recorded at one level has to be recorded again before it's written at another level.
+    fn update_recording(&mut self) {
+        let recording = self
+            .trail
+            .last()
+            .map_or(Recording::Off, |frame| frame.recording);
+        let journal = self.backtrackable_mem.journal_mut();
+        journal.set_recording(recording);
+        journal.clear_dirty();
+    }
+}
+
+/// A level of the trail.
+///
+/// A `Level` is returned by [`Trail::new_level()`](Trail::new_level) and identifies that level for
+/// as long as it stays on the trail. Once the level is removed, it's never confused with another
+/// level of the same depth.
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{BacktrackableValue, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let value = BacktrackableValue::new(&mut builder, 'a');
+/// let mut trail = builder.finish();
+///
+/// let first = trail.new_level();
+/// value.set(&mut trail, 'b');
+/// let second = trail.new_level();
+/// value.set(&mut trail, 'c');
+///
+/// assert_eq!(first.depth(), 1);
+/// assert_eq!(second.depth(), 2);
+///
+/// trail.backtrack_to(second);
+/// assert_eq!(value.get(&trail), 'b');
+///
+/// trail.backtrack_to(first);
+/// assert_eq!(value.get(&trail), 'a');
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Level {
+    depth: usize,
+    id: u64,
+}
+
+impl Level {
+    /// Returns the length of the trail right after the level was added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// let level = trail.new_level();
+    /// assert_eq!(level.depth(), trail.trail_len());
+    /// ```
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// Statistics about how a trail has been used, returned by [`Trail::stats()`](Trail::stats).
+///
+/// Every field is a counter since the trail was created or its statistics were last
+/// [reset](Trail::reset_stats), except for `level_stack_bytes`, which is the size of the level
+/// stack at the time the statistics were requested.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct TrailStats {
+    /// The number of levels added to the trail.
+    pub levels_created: usize,
+    /// The number of times the trail backtracked. Removing several levels at once with
+    /// [`backtrack_to()`](Trail::backtrack_to), [`reset_to_root()`](Trail::reset_to_root) or
+    /// [`restart()`](Trail::restart) counts as a single backtrack.
+    pub backtracks: usize,
+    /// The number of times the trail was [restarted](Trail::restart).
+    pub restarts: usize,
+    /// The maximum length of the trail.
+    pub max_depth: usize,
+    /// The number of bytes of backtrackable memory copied by levels that clone the memory and by
+    /// [snapshots](Trail::snapshot). Pages that a snapshot shares with the previous snapshot
+    /// aren't counted.
+    pub snapshot_bytes: usize,
+    /// The number of bytes currently held by the levels on the trail, counting both clones of the
+    /// backtrackable memory and the undo logs of backtrackable memory and of storage modes with
+    /// the [`Undo`](storage::Undo) policy.
+    pub level_stack_bytes: usize,
+    /// The number of writes to the memory of each storage mode on the trail, keyed by its
+    /// [name](StorageMode::NAME). Storage modes with the same name are counted together.
+    pub writes: BTreeMap<&'static str, usize>,
+}
+
+/// A guard that removes a level from the trail when dropped.
+///
+/// A `LevelGuard` is created by [`Trail::level_guard()`](Trail::level_guard), and dereferences to
+/// the trail.
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{BacktrackableValue, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let value = BacktrackableValue::new(&mut builder, 'a');
+/// let mut trail = builder.finish();
+///
+/// let mut guard = trail.level_guard();
+/// value.set(&mut guard, 'b');
+/// guard.commit();
+///
+/// assert_eq!(value.get(&trail), 'b');
+/// ```
+pub struct LevelGuard<'t> {
+    trail: &'t mut Trail,
+    level: Level,
+}
+
+impl<'t> LevelGuard<'t> {
+    /// Returns the level added when the guard was created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// let guard = trail.level_guard();
+    /// assert!(guard.contains_level(guard.level()));
+    /// ```
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Consumes the guard, keeping the changes made since its level was added.
+    ///
+    /// The guard's level and any levels added after it are removed from the trail as if by
+    /// [`Trail::commit_level()`](Trail::commit_level). If the level was already removed, this
+    /// method has no effect.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    ///
+    /// trail.new_level();
+    ///
+    /// let mut guard = trail.level_guard();
+    /// value.set(&mut guard, 1);
+    /// guard.commit();
+    ///
+    /// assert_eq!(value.get(&trail), 1);
+    /// assert_eq!(trail.trail_len(), 1);
+    ///
+    /// trail.backtrack();
+    /// assert_eq!(value.get(&trail), 0);
+    /// ```
+    pub fn commit(self) {
+        while self.trail.contains_level(self.level) {
+            self.trail.commit_level();
+        }
+    }
+}
+
+impl<'t> Deref for LevelGuard<'t> {
+    type Target = Trail;
+
+    fn deref(&self) -> &Trail {
+        self.trail
+    }
+}
+
+impl<'t> DerefMut for LevelGuard<'t> {
+    fn deref_mut(&mut self) -> &mut Trail {
+        self.trail
+    }
+}
+
+impl<'t> Drop for LevelGuard<'t> {
+    fn drop(&mut self) {
+        if self.trail.contains_level(self.level) {
+            self.trail.backtrack_to(self.level);
+        }
+    }
+}
+
+impl<'t> fmt::Debug for LevelGuard<'t> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LevelGuard")
+            .field("level", &self.level)
+            .finish()
+    }
+}
+
+/// A saved state of a trail's backtrackable memory.
+///
+/// A `Snapshot` is created by [`Trail::snapshot()`](Trail::snapshot) and can be restored with
+/// [`Trail::restore()`](Trail::restore) any number of times. Cloning a snapshot is cheap, since
+/// its pages are shared.
+#[derive(Clone)]
+pub struct Snapshot {
+    trail_id: u64,
+    pages: Vec<Arc<[u8]>>,
+    allocations: Arc<[(usize, Generation)]>,
+}
+
+impl Snapshot {
+    /// Returns the number of bytes saved in the snapshot.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// BacktrackableArray::new(&mut builder, vec![0u8; 1000]);
+    /// let mut trail = builder.finish();
+    ///
+    /// assert_eq!(trail.snapshot().len(), 1000);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.pages.iter().map(|page| page.len()).sum()
+    }
+
+    /// Checks if the snapshot is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// assert!(trail.snapshot().is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// The estimated cost of recording and undoing a byte written to backtrackable memory, relative to
+/// the cost of copying a byte of backtrackable memory when a level is added and removed.
+const ADAPTIVE_LOG_COST: usize = 4;
+
+/// A strategy for saving the state of backtrackable memory when a new level is added to the trail.
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{BacktrackableArray, Strategy, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::with_strategy(Strategy::Log);
+/// let array = BacktrackableArray::new(&mut builder, vec![0; 1_000_000]);
+/// let mut trail = builder.finish();
+///
+/// // the megabyte of backtrackable memory isn't copied here...
+/// trail.new_level();
+/// array.set(&mut trail, 10, 42);
+///
+/// // ...and only the overwritten bytes are restored here
+/// trail.backtrack();
+/// assert_eq!(array.get(&trail, 10), 0);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Strategy {
+    /// Clones the entire backtrackable memory whenever a new level is added.
+    ///
+    /// Adding a level takes time proportional to the size of the backtrackable memory, but
+    /// writes are as cheap as possible.
+    Copy,
+    /// Records the offset and previous contents of every write to backtrackable memory in an undo
+    /// log, which is replayed in reverse when backtracking.
+    ///
+    /// Adding a level takes constant time, and backtracking takes time proportional to the number
+    /// of writes made since the level was added. This is much faster than `Copy` when the
+    /// backtrackable memory is large but only a few values are written at each level.
+    Log,
+    /// Splits the backtrackable memory into fixed-size pages and records a copy of each page in
+    /// an undo log the first time it's written after a level is added.
+    ///
+    /// Like `Log`, adding a level takes constant time. Backtracking takes time proportional to the
+    /// number of distinct pages written since the level was added, so repeatedly writing to the
+    /// same values is much cheaper than with `Log`, while writing to a single value is more
+    /// expensive.
+    Paged,
+    /// Splits the backtrackable memory into 8-byte words, each stamped with the time it was last
+    /// recorded, and records a word in an undo log only on the first write to it after a level is
+    /// added.
+    ///
+    /// This is the lazy trailing of timestamped values: repeatedly writing to the same value at
+    /// a level costs a single comparison after the first write, and unlike `Paged`, writing to a
+    /// single value only records the words it spans. The stamps take up half as much space as
+    /// the backtrackable memory itself.
+    Stamped,
+    /// Chooses between `Copy` and `Log` whenever a new level is added.
+    ///
+    /// The trail keeps track of how many bytes were written at the most recently removed level.
+    /// If undoing that many writes would be cheaper than copying the entire backtrackable memory,
+    /// the new level uses `Log`; otherwise it uses `Copy`. This is the default strategy.
+    #[default]
+    Adaptive,
+}
+
+/// A builder to create a `Trail`.
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{BacktrackableValue, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let value = BacktrackableValue::new(&mut builder, 5);
+/// let trail = builder.finish();
+///
+/// assert_eq!(value.get(&trail), 5);
+/// ```
+#[derive(Debug, Default)]
+pub struct TrailBuilder {
+    backtrackable_mem: MemoryBuilder,
+    non_backtrackable_mem: MemoryBuilder,
+    strategy: Strategy,
+    layout: Layout,
+    regions: RegionBuilders,
+    constant_mem: MemoryBuilder,
+    restart_mem: MemoryBuilder,
+}
+
+impl TrailBuilder {
+    /// Creates a new empty `TrailBuilder`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::TrailBuilder;
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// ```
+    pub fn new() -> Self {
+        Self::with_strategy(Strategy::default())
+    }
+
+    /// Creates a new empty `TrailBuilder` for a trail that uses the given strategy to save
+    /// backtrackable memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, Strategy, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::with_strategy(Strategy::Log);
+    /// let value = BacktrackableValue::new(&mut builder, 1);
+    /// let mut trail = builder.finish();
+    ///
+    /// trail.new_level();
+    /// value.set(&mut trail, 2);
+    /// trail.backtrack();
+    /// assert_eq!(value.get(&trail), 1);
+    /// ```
+    pub fn with_strategy(strategy: Strategy) -> Self {
+        Self {
+            backtrackable_mem: MemoryBuilder::new(),
+            non_backtrackable_mem: MemoryBuilder::new(),
+            strategy,
+            layout: Layout::default(),
+            regions: RegionBuilders::default(),
+            constant_mem: MemoryBuilder::new(),
+            restart_mem: MemoryBuilder::new(),
+        }
+    }
+
+    /// Consumes the `TrailBuilder` to create a new `Trail`.
+    ///
+    /// Once this method is called, any `Value` and `Array` that were created using the
+    /// `TrailBuilder` are usable with the resulting `Trail`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{NonBacktrackableArray, NonBacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = NonBacktrackableValue::new(&mut builder, -123);
+    /// let array = NonBacktrackableArray::new(&mut builder, vec![1, 3, 5, 7]);
+    /// let trail = builder.finish();
+    ///
+    /// assert_eq!(value.get(&trail), -123);
+    /// assert_eq!(array.get(&trail, 2), 5);
+    /// ```
+    pub fn finish(self) -> Trail {
+        let restart_mem = self.restart_mem.finish();
+        let initial_restart_mem = restart_mem.clone();
+        Trail {
+            id: new_trail_id(),
+            backtrackable_mem: self.backtrackable_mem.finish(),
+            non_backtrackable_mem: self.non_backtrackable_mem.finish(),
+            strategy: self.strategy,
+            trail: vec![],
+            last_level_written: 0,
+            next_level_id: 0,
+            last_snapshot: vec![],
+            layout: self.layout,
+            stats: TrailStats::default(),
+            backtrack_actions: vec![],
+            watchers: Watchers::default(),
+            regions: self.regions.finish(),
+            constant_mem: Arc::new(self.constant_mem.finish()),
+            restart_mem,
+            initial_restart_mem,
+        }
+    }
+
+    /// Consumes the `TrailBuilder` and passes it to the given function as a
+    /// [branded](branded) builder.
+    ///
+    /// Every value created from the branded builder shares a unique lifetime brand with the
+    /// finished trail, so using a value with a different trail is a compile-time error instead of
+    /// a runtime check.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{branded::BacktrackableArray, Strategy, TrailBuilder};
+    ///
+    /// let sum = TrailBuilder::with_strategy(Strategy::Log).scope(|mut builder| {
+    ///     let array = BacktrackableArray::new(&mut builder, 1..=4);
+    ///     let trail = builder.finish();
+    ///
+    ///     array.iter(&trail).sum::<i32>()
+    /// });
+    ///
+    /// assert_eq!(sum, 10);
+    /// ```
+    pub fn scope<R>(self, f: impl for<'brand> FnOnce(branded::TrailBuilder<'brand>) -> R) -> R {
+        f(branded::TrailBuilder::new(self))
+    }
+}
+
+/// A reference to a value stored on the trail.
+///
+/// The type parameter `T` is the type of value stored on the trail, and the type parameter `M`
+/// represents how the value is stored on the trail.  A `Value<Backtrackable, T>` is stored on the
+/// trail in backtrackable memory, whereas a `Value<NonBacktrackable, T>` is stored on the trail in
+/// non-backtrackable memory.
+///
+/// Instead of using `Value` directly, it's often easier to use the type definitions
+/// [`BacktrackableValue`](BacktrackableValue) and [`NonBacktrackableValue`](NonBacktrackableValue).
+///
+/// A `Value` is only usable with the `Trail` from the `TrailBuilder` used to create it. Using it
+/// with any other trail panics in debug builds or when the `checked` feature is enabled, and is
+/// undefined behavior otherwise.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Value<M, T> {
+    pointer: Pointer<T>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    region: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    phantom: PhantomData<M>,
+}
+
+impl<M, T> Value<M, T>
+where
+    M: Readable,
+    T: Bytes,
+{
+    /// Creates a new `Value` with the given value.
+    ///
+    /// The `Value` is usable after the `TrailBuilder` used to create it is finished.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 'b');
+    /// let trail = builder.finish();
+    ///
+    /// // the value is usable now
+    /// assert_eq!(value.get(&trail), 'b');
+    /// ```
+    pub fn new(builder: &mut TrailBuilder, val: T) -> Self {
+        Self {
+            pointer: Pointer::new(M::builder_mut(builder), val),
+            region: M::region(builder),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new `Value` with the given value and records it under the given name in the
+    /// trail's [`Layout`](layout::Layout).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value with the same name was already created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::named(&mut builder, "x[3]", 0);
+    /// let trail = builder.finish();
+    ///
+    /// assert_eq!(trail.layout().get("x[3]").unwrap().type_name(), "i32");
+    /// assert_eq!(trail.inspect().to_string(), "x[3]: i32 = 0 (Backtrackable, bytes 0..4)\n");
+    /// ```
+    pub fn named(builder: &mut TrailBuilder, name: impl Into<String>, val: T) -> Self
+    where
+        T: fmt::Debug,
+    {
+        let value = Self::new(builder, val);
+        builder
+            .layout
+            .push::<M, T>(name.into(), value.region, value.pointer.range().start, None);
+        value
+    }
+
+    /// Gets the value from the trail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let mut trail = builder.finish();
+    ///
+    /// assert_eq!(value.get(&trail), 5);
+    /// ```
+    #[inline]
+    pub fn get(self, trail: &Trail) -> T {
+        self.pointer.get(M::memory(trail, self.region))
+    }
+}
+
+impl<M, T> Value<M, T>
+where
+    M: StorageMode,
+    T: Bytes,
+{
+    /// Sets the value on the trail.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let mut trail = builder.finish();
+    ///
+    /// value.set(&mut trail, 42);
+    /// assert_eq!(value.get(&trail), 42);
+    /// ```
+    #[inline]
+    pub fn set(self, trail: &mut Trail, new_val: T) {
+        let range = self.pointer.range();
+        trail.write::<M>(self.region, self.location(), range.len(), |memory| {
+            self.pointer.set(memory, new_val)
+        });
+    }
+
+    /// Updates the value on the trail using the given function.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let mut trail = builder.finish();
+    ///
+    /// value.update(&mut trail, |x| x * x);
+    /// assert_eq!(value.get(&trail), 25);
+    /// ```
+    #[inline]
+    pub fn update(self, trail: &mut Trail, f: impl FnOnce(T) -> T) {
+        self.set(trail, f(self.get(trail)));
+    }
+
+    /// Watches the value, so that writes to it add an event to the trail's queue of events.
+    ///
+    /// See the [`watch`](watch) module for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{watch::EventKind, BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 5);
+    /// let mut trail = builder.finish();
+    ///
+    /// let watch = value.watch(&mut trail, EventKind::Change);
+    ///
+    /// value.set(&mut trail, 5);
+    /// assert_eq!(trail.next_event(), None);
+    ///
+    /// value.set(&mut trail, 6);
+    /// assert_eq!(trail.next_event().unwrap().watch, watch);
+    /// ```
+    pub fn watch(self, trail: &mut Trail, kind: EventKind) -> WatchId {
+        trail.watchers.watch(self.location(), kind)
+    }
+
+    /// Returns the location of the value for watches.
+    #[inline]
+    fn location(self) -> Location {
+        Location::new::<M>(
+            self.region,
+            self.pointer.generation(),
+            self.pointer.range().start,
+        )
+    }
+}
+
+impl<T> Value<Backtrackable, T>
+where
+    T: Bytes,
+{
+    /// Allocates a new `Value` in the trail's backtrackable memory that lives until the current
+    /// level is removed.
+    ///
+    /// Once the current level is backtracked, the memory used by the value is freed and may be
+    /// reused by later allocations. Using the value after that point panics, even if the memory
+    /// has already been reused. If the level is committed, the value lives until the parent level
+    /// is removed. Values allocated when the trail has no levels live as long as the trail.
+    ///
+    /// [Restoring a snapshot](Trail::restore) frees every allocated value that didn't exist when
+    /// the snapshot was taken and brings back the values that did. Removing the level that the
+    /// snapshot was restored at undoes this.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// trail.new_level();
+    /// let scratch = BacktrackableValue::alloc(&mut trail, 7);
+    /// scratch.update(&mut trail, |x| x * 6);
+    /// assert_eq!(scratch.get(&trail), 42);
+    ///
+    /// // backtracking frees the value
+    /// trail.backtrack();
+    /// ```
+    pub fn alloc(trail: &mut Trail, val: T) -> Self {
+        Self {
+            pointer: Pointer::alloc(&mut trail.backtrackable_mem, val),
+            region: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the range of bytes in the trail's backtrackable memory where the value is stored.
+    ///
+    /// See [`Trail::changed_since()`](Trail::changed_since).
+    pub fn byte_range(self) -> Range<usize> {
+        self.pointer.range()
+    }
+
+    /// Gets the value as it was when the given level was added to the trail, without
+    /// backtracking.
+    ///
+    /// This is the value that [`backtrack_to(level)`](Trail::backtrack_to) would restore.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` has already been removed from the trail, or if the value was allocated
+    /// after `level` was added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableValue, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let value = BacktrackableValue::new(&mut builder, 0);
+    /// let mut trail = builder.finish();
+    ///
+    /// let parent = trail.new_level();
+    /// value.set(&mut trail, 1);
+    /// let child = trail.new_level();
+    /// value.set(&mut trail, 2);
+    ///
+    /// assert_eq!(value.get_at_level(&trail, parent), 0);
+    /// assert_eq!(value.get_at_level(&trail, child), 1);
+    /// assert_eq!(value.get(&trail), 2);
+    /// ```
+    pub fn get_at_level(self, trail: &Trail, level: Level) -> T {
+        self.pointer
+            .get_from(&trail.backtrackable_mem, |offset, len, generation| {
+                trail.bytes_at_level(level, offset, len, generation)
+            })
+    }
+}
+
+impl<M, T> Clone for Value<M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, T> Copy for Value<M, T> {}
+
+impl<M, T> fmt::Debug for Value<M, T>
+where
+    M: Readable,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Value")
+            .field("pointer", &self.pointer)
+            .field("storage_mode", &M::default())
+            .finish()
+    }
+}
+
+impl<M, T> Eq for Value<M, T> {}
+
+impl<M, T> PartialEq for Value<M, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.pointer == other.pointer
+    }
+}
+
+/// A reference to a fixed-length array of values stored on the trail.
+///
+/// The type parameter `T` is the type of value stored on the trail, and the type parameter `M`
+/// represents how the value is stored on the trail.  An `Array<Backtrackable, T>` is stored on the
+/// trail in backtrackable memory, whereas an `Array<NonBacktrackable, T>` is stored on the trail
+/// in non-backtrackable memory.
+///
+/// Instead of using `Array` directly, it's often easier to use the type definitions
+/// [`BacktrackableArray`](BacktrackableArray) and
+/// [`NonBacktrackableArray`](NonBacktrackableArray).
+///
+/// An `Array` is only usable with the `Trail` from the `TrailBuilder` used to create it. Using it
+/// with any other trail panics in debug builds or when the `checked` feature is enabled, and is
+/// undefined behavior otherwise.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(bound = ""))]
+pub struct Array<M, T> {
+    pointer: ArrayPointer<T>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    region: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    phantom: PhantomData<M>,
+}
+
+impl<M, T> Array<M, T>
+where
+    M: Readable,
+    T: Bytes,
+{
+    /// Creates a new `Array` with the given values.
+    ///
+    /// The `Array` is usable after the `TrailBuilder` used to create it is finished.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 5..10);
+    /// let trail = builder.finish();
+    ///
+    /// // the array is usable now
+    /// assert_eq!(array.get(&trail, 2), 7);
+    /// ```
+    pub fn new(builder: &mut TrailBuilder, vals: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            pointer: ArrayPointer::new(
+                M::builder_mut(builder),
+                &vals.into_iter().collect::<Vec<_>>(),
+            ),
+            region: M::region(builder),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new `Array` with the given values and records it under the given name in the
+    /// trail's [`Layout`](layout::Layout).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value with the same name was already created.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{NonBacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = NonBacktrackableArray::named(&mut builder, "primes", vec![2u8, 3, 5]);
+    /// let trail = builder.finish();
+    ///
+    /// assert_eq!(trail.layout().get("primes").unwrap().array_len(), Some(3));
+    /// assert_eq!(
+    ///     trail.inspect().to_string(),
+    ///     "primes: [u8; 3] = [2, 3, 5] (NonBacktrackable, bytes 0..3)\n",
+    /// );
+    /// ```
+    pub fn named(
+        builder: &mut TrailBuilder,
+        name: impl Into<String>,
+        vals: impl IntoIterator<Item = T>,
+    ) -> Self
+    where
+        T: fmt::Debug,
+    {
+        let array = Self::new(builder, vals);
+        builder.layout.push::<M, T>(
+            name.into(),
+            array.region,
+            array.pointer.range().start,
+            Some(array.len()),
+        );
+        array
+    }
+
+    /// Returns the length of the array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..8);
+    ///
+    /// assert_eq!(array.len(), 8);
+    /// ```
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pointer.len()
+    }
+
+    /// Checks if the length of the array is equal to 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let empty = BacktrackableArray::new(&mut builder, 0..0);
+    /// let not_empty = BacktrackableArray::new(&mut builder, 0..1);
+    ///
+    /// assert_eq!(empty.is_empty(), true);
+    /// assert_eq!(not_empty.is_empty(), false);
+    /// ```
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pointer.len() == 0
+    }
+
+    /// Returns an iterator over the elements of the array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let odds = BacktrackableArray::new(&mut builder, (0..10).map(|x| 2 * x + 1));
+    /// let trail = builder.finish();
+    ///
+    /// for odd in odds.iter(&trail) {
+    ///     assert_eq!(odd % 2, 1);
+    /// }
+    /// ```
+    pub fn iter<'t>(&self, trail: &'t Trail) -> ArrayIter<'t, M, T> {
+        ArrayIter {
+            trail,
+            index: 0,
+            array: *self,
+        }
+    }
+
+    /// Gets the value of the array at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, Trail, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..10);
+    /// let mut trail = builder.finish();
+    ///
+    /// assert_eq!(array.get(&trail, 4), 4);
+    /// ```
+    #[inline]
+    pub fn get(&self, trail: &Trail, i: usize) -> T {
+        self.pointer.get(M::memory(trail, self.region), i)
+    }
+
+    /// Returns the values of the array as a slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment of `T` is greater than [`MAX_ALIGN`](mem::MAX_ALIGN).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, vec![3u32, 1, 4]);
+    /// let mut trail = builder.finish();
+    ///
+    /// array.set(&mut trail, 1, 5);
+    /// assert_eq!(array.as_slice(&trail).iter().max(), Some(&5));
+    /// ```
+    #[inline]
+    pub fn as_slice<'t>(&self, trail: &'t Trail) -> &'t [T]
+    where
+        T: Plain,
+    {
+        self.pointer.as_slice(M::memory(trail, self.region))
+    }
+
+    /// Returns an `Array` of the values in the given range of the array.
+    ///
+    /// The returned array is a view of the same memory: writes through either array are visible
+    /// through the other.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..10);
+    /// let mut trail = builder.finish();
+    ///
+    /// let middle = array.slice(3..6);
+    /// assert_eq!(middle.len(), 3);
+    ///
+    /// middle.set(&mut trail, 0, -3);
+    /// assert_eq!(array.get(&trail, 3), -3);
+    /// ```
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        Self {
+            pointer: self.pointer.slice(range),
+            region: self.region,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Copies the values of the array to the given slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is different from the length of the array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, vec!['a', 'b', 'c']);
+    /// let trail = builder.finish();
+    ///
+    /// let mut buf = ['\0'; 2];
+    /// array.slice(1..).read_into(&trail, &mut buf);
+    /// assert_eq!(buf, ['b', 'c']);
+    /// ```
+    #[inline]
+    pub fn read_into(&self, trail: &Trail, out: &mut [T]) {
+        self.pointer.read_into(M::memory(trail, self.region), out);
+    }
+}
+
+impl<M, T> Array<M, T>
+where
+    M: StorageMode,
+    T: Bytes,
+{
+    /// Sets the value of the array at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, Trail, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..10);
+    /// let mut trail = builder.finish();
+    ///
+    /// assert_eq!(array.get(&trail, 4), 4);
+    ///
+    /// array.set(&mut trail, 4, -23);
+    /// assert_eq!(array.get(&trail, 4), -23);
+    /// ```
+    #[inline]
+    pub fn set(&self, trail: &mut Trail, i: usize, new_val: T) {
+        let offset = self
+            .pointer
+            .range()
+            .start
+            .wrapping_add(i.wrapping_mul(T::LENGTH));
+        trail.write::<M>(self.region, self.location(offset), T::LENGTH, |memory| {
+            self.pointer.set(memory, i, new_val)
+        });
+    }
+
+    /// Updates the value of the array at the given index using the given update function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, Trail, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..10);
+    /// let mut trail = builder.finish();
+    ///
+    /// assert_eq!(array.get(&trail, 4), 4);
+    ///
+    /// array.update(&mut trail, 4, |x| x * x);
+    /// assert_eq!(array.get(&trail, 4), 16);
+    /// ```
+    #[inline]
+    pub fn update(&self, trail: &mut Trail, i: usize, f: impl FnOnce(T) -> T) {
+        self.set(trail, i, f(self.get(trail, i)));
+    }
+
+    /// Swaps the two values at the given indices of the array in memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either of the indices are out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, vec!['r', 'u', 't', 's']);
+    /// let mut trail = builder.finish();
+    ///
+    /// assert_eq!(array.get(&trail, 2), 't');
+    /// assert_eq!(array.get(&trail, 3), 's');
+    ///
+    /// array.swap(&mut trail, 2, 3);
+    ///
+    /// assert_eq!(array.get(&trail, 2), 's');
+    /// assert_eq!(array.get(&trail, 3), 't');
+    /// ```
+    #[inline]
+    pub fn swap(&self, trail: &mut Trail, i: usize, j: usize) {
+        if trail.watchers.is_empty() {
+            self.pointer.swap(M::memory_mut(trail, self.region), i, j);
+        } else {
+            let (a, b) = (self.get(trail, i), self.get(trail, j));
+            self.set(trail, i, b);
+            self.set(trail, j, a);
+        }
+    }
+
+    /// Sets the values of the array to the values of the given slice.
+    ///
+    /// This is faster than setting the values one by one, and is recorded as a single write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is different from the length of the array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, vec![0; 4]);
+    /// let mut trail = builder.finish();
+    ///
+    /// trail.new_level();
+    /// array.write_from(&mut trail, &[4, 3, 2, 1]);
+    /// assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![4, 3, 2, 1]);
+    ///
+    /// trail.backtrack();
+    /// assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![0, 0, 0, 0]);
+    /// ```
+    #[inline]
+    pub fn write_from(&self, trail: &mut Trail, vals: &[T]) {
+        let range = self.pointer.range();
+        trail.write_elements::<M>(
+            self.region,
+            self.location(range.start),
+            range.len(),
+            T::LENGTH,
+            |memory| self.pointer.write_from(memory, vals),
+        );
+    }
+
+    /// Sets every value of the array to the given value.
+    ///
+    /// This is faster than setting the values one by one, and is recorded as a single write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..6);
+    /// let mut trail = builder.finish();
+    ///
+    /// array.slice(2..).fill(&mut trail, 0);
+    /// assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![0, 1, 0, 0, 0, 0]);
+    /// ```
+    #[inline]
+    pub fn fill(&self, trail: &mut Trail, val: T) {
+        let range = self.pointer.range();
+        trail.write_elements::<M>(
+            self.region,
+            self.location(range.start),
+            range.len(),
+            T::LENGTH,
+            |memory| self.pointer.fill(memory, val),
+        );
+    }
+
+    /// Copies the values in the range `src` of the array to the indices starting at `dest`.
+    ///
+    /// The ranges may overlap. The copy is recorded as a single write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either range is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, vec!['a', 'b', 'c', 'd']);
+    /// let mut trail = builder.finish();
+    ///
+    /// // shift the first three values one index to the right
+    /// array.copy_within(&mut trail, ..3, 1);
+    /// assert_eq!(array.iter(&trail).collect::<String>(), "aabc");
+    /// ```
+    pub fn copy_within(&self, trail: &mut Trail, src: impl RangeBounds<usize>, dest: usize) {
+        let src = mem::to_range(src, self.len());
+        let dest_end = dest
+            .checked_add(src.len())
+            .filter(|&end| end <= self.len())
+            .expect("destination range out of bounds");
+        let dest_range = self.pointer.slice(dest..dest_end).range();
+        trail.write_elements::<M>(
+            self.region,
+            self.location(dest_range.start),
+            dest_range.len(),
+            T::LENGTH,
+            |memory| self.pointer.copy_within(memory, src, dest),
+        );
+    }
+
+    /// Updates every value of the array in place using the given function.
+    ///
+    /// This is faster than updating the values one by one, since the bounds of the array are
+    /// only checked once, and is recorded as a single write.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..5);
+    /// let mut trail = builder.finish();
+    ///
+    /// array.for_each_mut(&mut trail, |x| *x *= *x);
+    /// assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![0, 1, 4, 9, 16]);
+    /// ```
+    #[inline]
+    pub fn for_each_mut(&self, trail: &mut Trail, f: impl FnMut(&mut T)) {
+        let range = self.pointer.range();
+        trail.write_elements::<M>(
+            self.region,
+            self.location(range.start),
+            range.len(),
+            T::LENGTH,
+            |memory| self.pointer.for_each_mut(memory, f),
+        );
+    }
+
+    /// Watches the value of the array at the given index, so that writes to it add an event to
+    /// the trail's queue of events.
+    ///
+    /// See the [`watch`](watch) module for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{watch::EventKind, NonBacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = NonBacktrackableArray::new(&mut builder, vec![0; 10]);
+    /// let mut trail = builder.finish();
+    ///
+    /// let watch = array.watch(&mut trail, 7, EventKind::Write);
+    ///
+    /// array.set(&mut trail, 6, 1);
+    /// assert_eq!(trail.next_event(), None);
+    ///
+    /// array.set(&mut trail, 7, 0);
+    /// assert_eq!(trail.next_event().unwrap().watch, watch);
+    /// ```
+    pub fn watch(&self, trail: &mut Trail, i: usize, kind: EventKind) -> WatchId {
+        assert!(i < self.len(), "array index out of bounds");
+        let offset = self.pointer.range().start + i * T::LENGTH;
+        trail.watchers.watch(self.location(offset), kind)
+    }
+
+    /// Returns the location of the value of the array at the given offset in memory for watches.
+    #[inline]
+    fn location(&self, offset: usize) -> Location {
+        Location::new::<M>(self.region, self.pointer.generation(), offset)
+    }
+}
+
+impl<T> Array<Backtrackable, T>
+where
+    T: Bytes,
+{
+    /// Allocates a new `Array` in the trail's backtrackable memory that lives until the current
+    /// level is removed.
+    ///
+    /// See [`Value::alloc`](Value::alloc) for how long the array lives.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut trail = TrailBuilder::new().finish();
+    ///
+    /// trail.new_level();
+    /// let cut = BacktrackableArray::alloc(&mut trail, vec![1, 0, 1]);
+    /// cut.swap(&mut trail, 1, 2);
+    /// assert_eq!(cut.iter(&trail).collect::<Vec<_>>(), vec![1, 1, 0]);
+    ///
+    /// // backtracking frees the array
+    /// trail.backtrack();
+    /// ```
+    pub fn alloc(trail: &mut Trail, vals: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            pointer: ArrayPointer::alloc(
+                &mut trail.backtrackable_mem,
+                &vals.into_iter().collect::<Vec<_>>(),
+            ),
+            region: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the range of bytes in the trail's backtrackable memory where the array is stored.
+    ///
+    /// See [`Trail::changed_since()`](Trail::changed_since).
+    pub fn byte_range(&self) -> Range<usize> {
+        self.pointer.range()
+    }
+
+    /// Gets the value of the array at the given index as it was when the given level was added
+    /// to the trail, without backtracking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds, if `level` has already been removed from the trail,
+    /// or if the array was allocated after `level` was added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::{BacktrackableArray, TrailBuilder};
+    ///
+    /// let mut builder = TrailBuilder::new();
+    /// let array = BacktrackableArray::new(&mut builder, 0..4);
+    /// let mut trail = builder.finish();
+    ///
+    /// let level = trail.new_level();
+    /// array.swap(&mut trail, 0, 3);
+    ///
+    /// assert_eq!(array.get_at_level(&trail, 0, level), 0);
+    /// assert_eq!(array.get(&trail, 0), 3);
+    /// ```
+    pub fn get_at_level(&self, trail: &Trail, i: usize, level: Level) -> T {
+        self.pointer
+            .get_from(&trail.backtrackable_mem, i, |offset, len, generation| {
+                trail.bytes_at_level(level, offset, len, generation)
+            })
+    }
+}
+
+impl<M, T> Clone for Array<M, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, T> Copy for Array<M, T> {}
+
+impl<M, T> fmt::Debug for Array<M, T>
+where
+    M: Readable,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Array")
+            .field("pointer", &self.pointer)
+            .field("storage_mode", &M::default())
+            .finish()
+    }
+}
+
+impl<M, T> Eq for Array<M, T> {}
+
+impl<M, T> PartialEq for Array<M, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.pointer == other.pointer
+    }
+}
+
+/// An iterator over the values of an `Array`.
+pub struct ArrayIter<'t, M, T> {
+    trail: &'t Trail,
+    index: usize,
+    array: Array<M, T>,
+}
+
+impl<'t, M, T> Iterator for ArrayIter<'t, M, T>
+where
+    M: Readable,
+    T: Bytes,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.index == self.array.len() {
+            None
+        } else {
+            let to_ret = Some(self.array.get(self.trail, self.index));
+            self.index += 1;
+            to_ret
+        }
+    }
+}
+
+/// A value stored on the trail in backtrackable memory.
+pub type BacktrackableValue<T> = Value<Backtrackable, T>;
+
+/// A value stored on the trail in non-backtrackable memory.
+pub type NonBacktrackableValue<T> = Value<NonBacktrackable, T>;
+
+/// A fixed-length array stored on the trail in backtrackable memory.
+pub type BacktrackableArray<T> = Array<Backtrackable, T>;
+
+/// A fixed-length array stored on the trail in non-backtrackable memory.
+pub type NonBacktrackableArray<T> = Array<NonBacktrackable, T>;
+
+/// A value stored on the trail in constant memory.
+pub type ConstantValue<T> = Value<Constant, T>;
+
+/// A fixed-length array stored on the trail in constant memory.
+pub type ConstantArray<T> = Array<Constant, T>;
+
+/// A value stored on the trail in restart memory.
+pub type RestartValue<T> = Value<Restart, T>;
+
+/// A fixed-length array stored on the trail in restart memory.
+pub type RestartArray<T> = Array<Restart, T>;
+
+#[cfg(test)]
+#[allow(clippy::unnecessary_mut_passed, clippy::useless_vec)]
+mod tests {
+    use super::*;
+
+    /// Returns every strategy, for tests that check that they all behave the same.
+    pub(crate) fn all_strategies() -> [Strategy; 5] {
+        // fails to compile if a strategy is added without adding it below
+        match Strategy::Copy {
+            Strategy::Copy
+            | Strategy::Log
+            | Strategy::Paged
+            | Strategy::Stamped
+            | Strategy::Adaptive => {}
+        }
+        [
+            Strategy::Copy,
+            Strategy::Log,
+            Strategy::Paged,
+            Strategy::Stamped,
+            Strategy::Adaptive,
+        ]
+    }
+
+    mod trail {
+        use super::*;
+
+        // runs the same sequence of writes and backtracks with every strategy
+        fn nested_levels(strategy: Strategy) {
+            let mut builder = TrailBuilder::with_strategy(strategy);
+            let value = BacktrackableValue::new(&mut builder, 0);
+            let array = BacktrackableArray::new(&mut builder, 0..5);
+            let stored = NonBacktrackableValue::new(&mut builder, 0);
+            let mut trail = builder.finish();
+
+            value.set(&mut trail, 1);
+
+            trail.new_level();
+            value.set(&mut trail, 2);
+            value.set(&mut trail, 3);
+            array.set(&mut trail, 1, 10);
+            stored.set(&mut trail, 1);
+
+            trail.new_level();
+            array.swap(&mut trail, 1, 4);
+            value.update(&mut trail, |x| x * 2);
+
+            assert_eq!(value.get(&trail), 6);
+            assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![0, 4, 2, 3, 10]);
+
+            trail.backtrack();
+
+            assert_eq!(value.get(&trail), 3);
+            assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![0, 10, 2, 3, 4]);
+
+            trail.backtrack();
+
+            assert_eq!(value.get(&trail), 1);
+            assert_eq!(array.iter(&trail).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+            assert_eq!(stored.get(&trail), 1);
+
+            // backtracking an empty trail has no effect
+            trail.backtrack();
+            assert_eq!(value.get(&trail), 1);
+        }
+
+        #[test]
+        fn copy() {
+            nested_levels(Strategy::Copy);
+        }
+
+        #[test]
+        fn log() {
+            nested_levels(Strategy::Log);
+        }
+
+        #[test]
+        fn paged() {
+            nested_levels(Strategy::Paged);
+        }
+
+        #[test]
+        fn stamped() {
+            nested_levels(Strategy::Stamped);
+        }
+
+        #[test]
+        fn adaptive() {
+            nested_levels(Strategy::Adaptive);
+        }
+
+        #[test]
+        fn stamped_records_each_word_once_per_level() {
+            let mut builder = TrailBuilder::with_strategy(Strategy::Stamped);
+            let array = BacktrackableArray::new(&mut builder, vec![0u32; 100]);
+            let mut trail = builder.finish();
+
+            trail.new_level();
+            for _ in 0..10 {
+                array.update(&mut trail, 3, |x| x + 1);
+            }
+            assert_eq!(trail.backtrackable_mem.journal().mark(), 1);
+            assert_eq!(
+                trail.stats().level_stack_bytes,
+                trail.backtrackable_mem.journal().size()
+            );
+
+            // the same word is recorded again at a new level, and again after backtracking to the
+            // first level
+            trail.new_level();
+            array.set(&mut trail, 2, 100);
+            assert_eq!(trail.backtrackable_mem.journal().mark(), 2);
+            trail.backtrack();
+            array.set(&mut trail, 2, 20);
+            assert_eq!(trail.backtrackable_mem.journal().mark(), 2);
+
+            assert_eq!(array.get(&trail, 2), 20);
+            assert_eq!(array.get(&trail, 3), 10);
+            trail.backtrack();
+            assert!(array.iter(&trail).all(|x| x == 0));
+        }
+
+        #[test]
+        fn paged_records_each_page_once_per_level() {
+            let mut builder = TrailBuilder::with_strategy(Strategy::Paged);
+            let array = BacktrackableArray::new(&mut builder, vec![0u8; 1000]);
+            let mut trail = builder.finish();
+
+            trail.new_level();
+            for _ in 0..10 {
+                for i in 0..array.len() {
+                    array.update(&mut trail, i, |x| x + 1);
+                }
+            }
+            let pages = trail.backtrackable_mem.journal_mut().mark();
+            assert_eq!(pages, array.len().div_ceil(journal::PAGE_SIZE));
+
+            // the same pages are recorded again at a new level
+            trail.new_level();
+            array.set(&mut trail, 0, 100);
+            array.set(&mut trail, 1, 100);
+            assert_eq!(trail.backtrackable_mem.journal_mut().mark(), pages + 1);
+
+            trail.backtrack();
+            assert!(array.iter(&trail).all(|x| x == 10));
+
+            trail.backtrack();
+            assert!(array.iter(&trail).all(|x| x == 0));
+        }
+
+        #[test]
+        fn adaptive_switches() {
+            let mut builder = TrailBuilder::new();
+            let array = BacktrackableArray::new(&mut builder, vec![0u8; 100]);
+            let mut trail = builder.finish();
+
+            assert_eq!(trail.strategy(), Strategy::Adaptive);
+
+            // nothing has been written yet, so the first level is logged
+            trail.new_level();
+            assert!(trail.trail[0].snapshot.is_none());
+            for i in 0..array.len() {
+                array.set(&mut trail, i, 1);
+            }
+            trail.backtrack();
+
+            // the whole array was written, so the next level is copied
+            trail.new_level();
+            assert!(trail.trail[0].snapshot.is_some());
+            array.set(&mut trail, 0, 1);
+
+            // writes made at a child level aren't counted for the parent level
+            trail.new_level();
+            for i in 0..array.len() {
+                array.set(&mut trail, i, 2);
+            }
+            trail.backtrack();
+            trail.backtrack();
+            assert_eq!(trail.last_level_written, 1);
+
+            // only a single byte was written, so the next level is logged again
+            trail.new_level();
+            assert!(trail.trail[0].snapshot.is_none());
+            trail.backtrack();
+
+            assert!(array.iter(&trail).all(|x| x == 0));
+        }
+
+        // backtracks to a level below levels saved with every strategy
+        fn backtrack_to_mixed(strategies: &[Strategy]) {
+            let mut builder = TrailBuilder::new();
+            let array = BacktrackableArray::new(&mut builder, vec![0; 300]);
+            let mut trail = builder.finish();
+
+            let mut levels = vec![];
+            for (i, &strategy) in strategies.iter().enumerate() {
+                trail.strategy = strategy;
+                levels.push(trail.new_level());
+                for j in 0..array.len() {
+                    array.set(&mut trail, j, i + 1);
+                }
+            }
+
+            trail.backtrack_to(levels[2]);
+            assert_eq!(trail.trail_len(), 2);
+            assert!(array.iter(&trail).all(|x| x == 2));
+            assert!(!trail.contains_level(levels[2]));
+            assert!(trail.contains_level(levels[1]));
+
+            trail.reset_to_root();
+            assert!(trail.is_trail_empty());
+            assert!(array.iter(&trail).all(|x| x == 0));
+        }
+
+        #[test]
+        fn backtrack_to() {
+            use Strategy::*;
+
+            backtrack_to_mixed(&[Copy, Copy, Copy, Copy, Copy]);
+            backtrack_to_mixed(&[Log, Log, Log, Log, Log]);
+            backtrack_to_mixed(&[Log, Paged, Copy, Log, Copy]);
+            backtrack_to_mixed(&[Copy, Log, Log, Copy, Paged]);
+            backtrack_to_mixed(&[Paged, Copy, Paged, Log, Log]);
+            backtrack_to_mixed(&[Stamped, Paged, Stamped, Copy, Stamped]);
+        }
+
+        // commits a level saved with one strategy into a level saved with another
+        fn commit_mixed(parent: Strategy, child: Strategy) {
+            let mut builder = TrailBuilder::new();
+            let array = BacktrackableArray::new(&mut builder, vec![0; 300]);
+            let mut trail = builder.finish();
+
+            trail.strategy = parent;
+            trail.new_level();
+            array.set(&mut trail, 0, 1);
+
+            trail.strategy = child;
+            trail.new_level();
+            array.set(&mut trail, 1, 2);
+            array.set(&mut trail, 299, 2);
+            trail.commit_level();
+
+            assert_eq!(trail.trail_len(), 1);
+            assert_eq!(array.get(&trail, 0), 1);
+            assert_eq!(array.get(&trail, 1), 2);
+            assert_eq!(array.get(&trail, 299), 2);
+
+            array.set(&mut trail, 2, 3);
+            trail.backtrack();
+            assert!(array.iter(&trail).all(|x| x == 0));
+
+            // committing the only level keeps every change
+            trail.new_level();
+            array.set(&mut trail, 0, 4);
+            trail.commit_level();
+            assert!(trail.is_trail_empty());
+            assert_eq!(array.get(&trail, 0), 4);
+            assert_eq!(trail.backtrackable_mem.journal_mut().mark(), 0);
+        }
+
+        #[test]
+        fn commit_level() {
+            for &parent in &all_strategies() {
+                for &child in &all_strategies() {
+                    commit_mixed(parent, child);
+                }
+            }
+        }
+
+        #[test]
+        fn level_guard_unwind() {
+            use std::panic::{catch_unwind, AssertUnwindSafe};
+
+            let mut builder = TrailBuilder::new();
+            let value = BacktrackableValue::new(&mut builder, 0);
+            let mut trail = builder.finish();
+
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                trail.probe(|trail| {
+                    value.set(trail, 1);
+                    trail.new_level();
+                    value.set(trail, 2);
+                    panic!("oops");
+                })
+            }));
+
+            assert!(result.is_err());
+            assert!(trail.is_trail_empty());
+            assert_eq!(value.get(&trail), 0);
+        }
+
+        #[test]
+        fn level_guard_removed_level() {
+            let mut builder = TrailBuilder::new();
+            let value = BacktrackableValue::new(&mut builder, 0);
+            let mut trail = builder.finish();
+
+            trail.new_level();
+            value.set(&mut trail, 1);
+
+            let mut guard = trail.level_guard();
+            value.set(&mut guard, 2);
+            guard.backtrack();
+            guard.new_level();
+            value.set(&mut guard, 3);
+            drop(guard);
+
+            // the guard's level was already removed, so the later level is kept
+            assert_eq!(trail.trail_len(), 2);
+            assert_eq!(value.get(&trail), 3);
+        }
+
+        #[test]
+        fn transaction_commits_nested_levels() {
+            let mut builder = TrailBuilder::new();
+            let value = BacktrackableValue::new(&mut builder, 0);
+            let mut trail = builder.finish();
+
+            let result: Result<(), ()> = trail.transaction(|trail| {
+                value.set(trail, 1);
+                trail.new_level();
+                value.set(trail, 2);
+                Ok(())
+            });
+
+            assert_eq!(result, Ok(()));
+            assert!(trail.is_trail_empty());
+            assert_eq!(value.get(&trail), 2);
+        }
+
+        #[test]
+        #[should_panic(expected = "level has already been removed from the trail")]
+        fn backtrack_to_removed() {
+            let mut trail = TrailBuilder::new().finish();
+
+            let level = trail.new_level();
+            trail.backtrack();
+            trail.new_level();
+            trail.backtrack_to(level);
+        }
+
+        #[test]
+        fn log_root_writes_not_recorded() {
+            let mut builder = TrailBuilder::with_strategy(Strategy::Log);
+            let value = BacktrackableValue::new(&mut builder, 0);
+            let mut trail = builder.finish();
+
+            for i in 0..10 {
+                value.set(&mut trail, i);
+            }
+            assert_eq!(trail.backtrackable_mem.journal_mut().mark(), 0);
+
+            trail.new_level();
+            value.set(&mut trail, 10);
+            assert_eq!(trail.backtrackable_mem.journal_mut().mark(), 1);
+
+            trail.backtrack();
+            assert_eq!(trail.backtrackable_mem.journal_mut().mark(), 0);
+            assert_eq!(value.get(&trail), 9);
+        }
+
+        #[test]
+        fn extend() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let value = BacktrackableValue::new(&mut builder, 1);
+                let mut trail = builder.finish();
+
+                trail.new_level();
+                value.set(&mut trail, 2);
+                trail.backtrack();
+
+                let array = trail.extend(|builder| BacktrackableArray::new(builder, 0..1000));
+                assert_eq!(value.get(&trail), 1);
+
+                trail.new_level();
+                value.set(&mut trail, 3);
+                array.set(&mut trail, 999, -1);
+                trail.backtrack();
+
+                assert_eq!(value.get(&trail), 1);
+                assert_eq!(array.get(&trail, 999), 999);
+            }
+        }
+
+        #[test]
+        fn alloc() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let value = BacktrackableValue::new(&mut builder, 0);
+                let mut trail = builder.finish();
+
+                trail.new_level();
+                let array = BacktrackableArray::alloc(&mut trail, 0..300);
+                array.set(&mut trail, 299, -1);
+
+                trail.new_level();
+                let scratch = BacktrackableValue::alloc(&mut trail, 5);
+                value.set(&mut trail, 1);
+                array.set(&mut trail, 0, -1);
+                array.set(&mut trail, 299, -2);
+                trail.backtrack();
+
+                assert_eq!(trail.backtrackable_mem.len(), 4 + 300 * 4);
+                assert_eq!(value.get(&trail), 0);
+                assert_eq!(array.get(&trail, 0), 0);
+                assert_eq!(array.get(&trail, 299), -1);
+
+                // a committed allocation is freed with the parent level
+                trail.new_level();
+                let scratch_again = BacktrackableValue::alloc(&mut trail, 6);
+                assert_eq!(scratch_again.byte_range(), scratch.byte_range());
+                assert_ne!(scratch_again, scratch);
+                assert_eq!(scratch_again.get(&trail), 6);
+                trail.commit_level();
+                assert_eq!(scratch_again.get(&trail), 6);
+
+                trail.backtrack();
+                assert_eq!(trail.backtrackable_mem.len(), 4);
+                assert_eq!(value.get(&trail), 0);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "pointer used after the memory it points to was freed")]
+        fn alloc_freed() {
+            let mut trail = TrailBuilder::new().finish();
+            trail.new_level();
+            let value = BacktrackableValue::alloc(&mut trail, 0);
+            trail.backtrack();
+            value.get(&trail);
+        }
+
+        #[test]
+        #[should_panic(expected = "pointer used after the memory it points to was freed")]
+        fn alloc_reused() {
+            let mut trail = TrailBuilder::new().finish();
+            trail.new_level();
+            let flag = BacktrackableValue::alloc(&mut trail, true);
+            trail.backtrack();
+
+            trail.new_level();
+            let byte = BacktrackableValue::alloc(&mut trail, 7u8);
+            assert_eq!(byte.byte_range(), flag.byte_range());
+            flag.get(&trail);
+        }
+
+        #[test]
+        fn alloc_restore() {
+            let mut trail = TrailBuilder::new().finish();
+            let empty = trail.snapshot();
+
+            trail.new_level();
+            let value = BacktrackableValue::alloc(&mut trail, 1u32);
+            let with_value = trail.snapshot();
+
+            // restoring a snapshot without the value frees it until the level is removed
+            trail.new_level();
+            trail.restore(&empty);
+            assert!(trail.backtrackable_mem.allocations().is_empty());
+            let other = BacktrackableValue::alloc(&mut trail, 2u32);
+            assert_eq!(other.byte_range(), value.byte_range());
+            trail.backtrack();
+            assert_eq!(trail.backtrackable_mem.allocations().len(), 1);
+            assert_eq!(value.get(&trail), 1);
+
+            // restoring a snapshot with the value brings it back
+            trail.backtrack();
+            assert!(trail.backtrackable_mem.allocations().is_empty());
+            trail.new_level();
+            trail.restore(&with_value);
+            assert_eq!(value.get(&trail), 1);
+        }
+
+        #[test]
+        #[should_panic(expected = "pointer used after the memory it points to was freed")]
+        fn alloc_restore_get_at_level() {
+            let mut trail = TrailBuilder::new().finish();
+            let empty = trail.snapshot();
+
+            trail.new_level();
+            let value = BacktrackableValue::alloc(&mut trail, 1u32);
+            let level = trail.new_level();
+            trail.restore(&empty);
+            let flag = BacktrackableValue::alloc(&mut trail, true);
+            assert_eq!(value.get_at_level(&trail, level), 1);
+
+            // the flag's bytes held the value when the level was added
+            flag.get_at_level(&trail, level);
+        }
+
+        #[test]
+        fn snapshot_shares_pages() {
+            let mut builder = TrailBuilder::new();
+            let array = BacktrackableArray::new(&mut builder, vec![0u8; 3 * PAGE_SIZE]);
+            let mut trail = builder.finish();
+
+            let first = trail.snapshot();
+            array.set(&mut trail, PAGE_SIZE, 1);
+            let second = trail.snapshot();
+
+            assert!(Arc::ptr_eq(&first.pages[0], &second.pages[0]));
+            assert!(!Arc::ptr_eq(&first.pages[1], &second.pages[1]));
+            assert!(Arc::ptr_eq(&first.pages[2], &second.pages[2]));
+
+            trail.restore(&first);
+            assert_eq!(array.get(&trail, PAGE_SIZE), 0);
+            trail.restore(&second);
+            assert_eq!(array.get(&trail, PAGE_SIZE), 1);
+        }
+
+        #[test]
+        fn restore_at_level() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let value = BacktrackableValue::new(&mut builder, 0);
+                let mut trail = builder.finish();
+
+                trail.new_level();
+                let scratch = BacktrackableValue::alloc(&mut trail, 1);
+                let snapshot = trail.snapshot();
+                trail.backtrack();
+
+                trail.new_level();
+                value.set(&mut trail, 2);
+                trail.restore(&snapshot);
+                assert_eq!(value.get(&trail), 0);
+                assert_eq!(scratch.get(&trail), 1);
+
+                trail.new_level();
+                value.set(&mut trail, 3);
+                trail.backtrack();
+                assert_eq!(value.get(&trail), 0);
+
+                trail.backtrack();
+                assert_eq!(trail.backtrackable_mem.len(), 4);
+                assert_eq!(value.get(&trail), 0);
+            }
+        }
+
+        #[test]
+        fn restore_smaller_at_level() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let value = BacktrackableValue::new(&mut builder, 0);
+                let mut trail = builder.finish();
+
+                let snapshot = trail.snapshot();
+                trail.new_level();
+                let scratch = BacktrackableValue::alloc(&mut trail, 1);
+                value.set(&mut trail, 2);
+                trail.new_level();
+                trail.restore(&snapshot);
+                assert_eq!(trail.backtrackable_mem.len(), 4);
+                assert_eq!(value.get(&trail), 0);
+
+                trail.backtrack();
+                assert_eq!(trail.backtrackable_mem.len(), 8);
+                assert_eq!(value.get(&trail), 2);
+                assert_eq!(scratch.get(&trail), 1);
+
+                trail.backtrack();
+                assert_eq!(trail.backtrackable_mem.len(), 4);
+                assert_eq!(value.get(&trail), 0);
+            }
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "snapshot restored to a trail other than the trail it was taken from"
+        )]
+        fn restore_other_trail() {
+            let snapshot = TrailBuilder::new().finish().snapshot();
+            TrailBuilder::new().finish().restore(&snapshot);
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "snapshot restored to a trail other than the trail it was taken from"
+        )]
+        fn restore_fork() {
+            let mut trail = TrailBuilder::new().finish();
+            let snapshot = trail.snapshot();
+            trail.fork().restore(&snapshot);
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "snapshot restored to a trail other than the trail it was taken from"
+        )]
+        fn restore_after_extend() {
+            let mut trail = TrailBuilder::new().finish();
+            let snapshot = trail.snapshot();
+            trail.extend(|builder| BacktrackableValue::new(builder, true));
+            trail.restore(&snapshot);
+        }
+
+        #[test]
+        fn get_at_level_mixed() {
+            for &strategies in &[
+                [Strategy::Copy, Strategy::Log, Strategy::Paged],
+                [Strategy::Log, Strategy::Copy, Strategy::Log],
+                [Strategy::Paged, Strategy::Paged, Strategy::Copy],
+                [Strategy::Stamped, Strategy::Log, Strategy::Stamped],
+            ] {
+                let mut builder = TrailBuilder::new();
+                let array = BacktrackableArray::new(&mut builder, vec![0; 300]);
+                let mut trail = builder.finish();
+
+                let mut levels = vec![];
+                for (i, &strategy) in strategies.iter().enumerate() {
+                    trail.strategy = strategy;
+                    levels.push(trail.new_level());
+                    array.set(&mut trail, 0, i + 1);
+                    array.set(&mut trail, 299, i + 1);
+                }
+                // a committed level's writes belong to its parent
+                trail.new_level();
+                array.set(&mut trail, 0, 10);
+                trail.commit_level();
+
+                for (i, &level) in levels.iter().enumerate() {
+                    assert_eq!(array.get_at_level(&trail, 0, level), i);
+                    assert_eq!(array.get_at_level(&trail, 299, level), i);
+                }
+                assert_eq!(array.get(&trail, 0), 10);
+                assert_eq!(array.get(&trail, 299), 3);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "value was allocated after the level was added")]
+        fn get_at_level_allocated() {
+            let mut trail = TrailBuilder::new().finish();
+            let level = trail.new_level();
+            let value = BacktrackableValue::alloc(&mut trail, 0);
+            value.get_at_level(&trail, level);
+        }
+
+        #[test]
+        fn changed_since() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let array = BacktrackableArray::new(&mut builder, vec![0u8; 600]);
+                let mut trail = builder.finish();
+
+                let level = trail.new_level();
+                array.set(&mut trail, 1, 1);
+                array.set(&mut trail, 2, 1);
+                trail.new_level();
+                array.set(&mut trail, 300, 1);
+                let value = BacktrackableValue::alloc(&mut trail, 0u16);
+
+                assert_eq!(trail.changed_since(level), vec![1..3, 300..301, 600..602]);
+                assert_eq!(value.byte_range(), 600..602);
+
+                trail.backtrack();
+                array.set(&mut trail, 599, 1);
+                assert_eq!(trail.changed_since(level), vec![1..3, 599..600]);
+            }
+        }
+
+        #[test]
+        fn changed_since_shrunk() {
+            for &strategy in &all_strategies() {
+                let mut trail = TrailBuilder::with_strategy(strategy).finish();
+                let empty = trail.snapshot();
+                trail.new_level();
+                BacktrackableValue::alloc(&mut trail, 0u32);
+                let level = trail.new_level();
+
+                trail.restore(&empty);
+                assert_eq!(trail.changed_since(level), vec![0..4]);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "values can only be added to a trail with no levels")]
+        fn extend_with_levels() {
+            let mut trail = TrailBuilder::new().finish();
+            trail.new_level();
+            trail.extend(|builder| BacktrackableValue::new(builder, 0));
+        }
+
+        #[test]
+        fn stats() {
+            let mut builder = TrailBuilder::with_strategy(Strategy::Log);
+            let array = BacktrackableArray::new(&mut builder, vec![0u8; 2 * PAGE_SIZE]);
+            let mut trail = builder.finish();
+
+            trail.new_level();
+            array.set(&mut trail, 0, 1);
+            trail.new_level();
+            array.set(&mut trail, 1, 1);
+            array.set(&mut trail, 2, 1);
+
+            let stats = trail.stats();
+            assert_eq!(stats.max_depth, 2);
+            assert_eq!(stats.snapshot_bytes, 0);
+            assert_eq!(stats.writes["Backtrackable"], 3);
+            assert_eq!(
+                stats.level_stack_bytes,
+                trail.backtrackable_mem.journal().size()
+            );
+            assert!(stats.level_stack_bytes >= 3);
+
+            // only the page that changed since the last snapshot is copied
+            trail.snapshot();
+            array.set(&mut trail, PAGE_SIZE, 1);
+            trail.snapshot();
+            assert_eq!(trail.stats().snapshot_bytes, 3 * PAGE_SIZE);
+
+            trail.reset_to_root();
+            let stats = trail.stats();
+            assert_eq!(stats.backtracks, 1);
+            assert_eq!(stats.level_stack_bytes, 0);
+
+            trail.reset_stats();
+            let writes = ["Backtrackable", "NonBacktrackable", "Restart"]
+                .iter()
+                .map(|&name| (name, 0))
+                .collect();
+            assert_eq!(
+                trail.stats(),
+                TrailStats {
+                    writes,
+                    ..TrailStats::default()
+                }
+            );
+        }
+
+        #[test]
+        fn on_backtrack() {
+            let push = |trail: &mut Trail, i: u32| {
+                trail.on_backtrack_with(move |_, log: &mut Vec<u32>| log.push(i));
+            };
+            let mut trail = TrailBuilder::new().finish();
+            let mut log: Vec<u32> = vec![];
+
+            // nothing to backtrack at the root
+            push(&mut trail, 0);
+
+            let first = trail.new_level();
+            push(&mut trail, 1);
+            trail.new_level();
+            push(&mut trail, 2);
+            trail.new_level();
+            push(&mut trail, 3);
+            push(&mut trail, 4);
+
+            // the actions of a committed level run with its parent
+            trail.commit_level();
+            trail.new_level();
+            push(&mut trail, 5);
+            trail.backtrack_with(&mut log);
+            assert_eq!(log, vec![5]);
+
+            trail.backtrack_to_with(first, &mut log);
+            assert_eq!(log, vec![5, 4, 3, 2, 1]);
+
+            // actions committed to the root never run
+            trail.new_level();
+            push(&mut trail, 6);
+            trail.commit_level();
+            trail.new_level();
+            trail.backtrack_with(&mut log);
+            assert_eq!(log.len(), 5);
+        }
+
+        #[test]
+        #[should_panic(
+            expected = "backtrack action expects external state of type `alloc::vec::Vec<u32>`"
+        )]
+        fn on_backtrack_without_ext() {
+            let mut trail = TrailBuilder::new().finish();
+
+            trail.new_level();
+            trail.on_backtrack_with(|_, log: &mut Vec<u32>| log.push(0));
+            trail.backtrack();
         }
-    }
 
-    /// Gets the value of the array at the given index.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the index is out of bounds.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use contrail::{BacktrackableArray, Trail, TrailBuilder};
-    ///
-    /// let mut builder = TrailBuilder::new();
-    /// let array = BacktrackableArray::new(&mut builder, 0..10);
-    /// let mut trail = builder.finish();
-    ///
-    /// assert_eq!(array.get(&trail, 4), 4);
-    /// ```
-    #[inline]
-    pub fn get(&self, trail: &Trail, i: usize) -> T {
-        self.pointer.get(M::memory(trail), i)
-    }
+        #[test]
+        fn on_backtrack_trail() {
+            let mut builder = TrailBuilder::new();
+            let value = BacktrackableValue::new(&mut builder, 1);
+            let seen = NonBacktrackableArray::new(&mut builder, vec![0; 2]);
+            let mut trail = builder.finish();
 
-    /// Sets the value of the array at the given index.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the index is out of bounds.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use contrail::{BacktrackableArray, Trail, TrailBuilder};
-    ///
-    /// let mut builder = TrailBuilder::new();
-    /// let array = BacktrackableArray::new(&mut builder, 0..10);
-    /// let mut trail = builder.finish();
-    ///
-    /// assert_eq!(array.get(&trail, 4), 4);
-    ///
-    /// array.set(&mut trail, 4, -23);
-    /// assert_eq!(array.get(&trail, 4), -23);
-    /// ```
-    #[inline]
-    pub fn set(&self, trail: &mut Trail, i: usize, new_val: T) {
-        self.pointer.set(M::memory_mut(trail), i, new_val);
-    }
+            trail.new_level();
+            value.set(&mut trail, 2);
+            trail.on_backtrack(move |trail| seen.set(trail, 0, value.get(trail)));
+            trail.new_level();
+            value.set(&mut trail, 3);
+            trail.on_backtrack(move |trail| seen.set(trail, 1, value.get(trail)));
 
-    /// Updates the value of the array at the given index using the given update function.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the index is out of bounds.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use contrail::{BacktrackableArray, Trail, TrailBuilder};
-    ///
-    /// let mut builder = TrailBuilder::new();
-    /// let array = BacktrackableArray::new(&mut builder, 0..10);
-    /// let mut trail = builder.finish();
-    ///
-    /// assert_eq!(array.get(&trail, 4), 4);
-    ///
-    /// array.update(&mut trail, 4, |x| x * x);
-    /// assert_eq!(array.get(&trail, 4), 16);
-    /// ```
-    #[inline]
-    pub fn update(&self, trail: &mut Trail, i: usize, f: impl FnOnce(T) -> T) {
-        self.pointer.update(M::memory_mut(trail), i, f);
-    }
+            // each action sees the trail after its level was removed
+            trail.backtrack();
+            assert_eq!(seen.as_slice(&trail), &[0, 2]);
+            trail.backtrack();
+            assert_eq!(seen.as_slice(&trail), &[1, 2]);
+        }
 
-    /// Swaps the two values at the given indices of the array in memory.
-    ///
-    /// # Panics
-    ///
-    /// Panics if either of the indices are out of bounds.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use contrail::{BacktrackableArray, TrailBuilder};
-    ///
-    /// let mut builder = TrailBuilder::new();
-    /// let array = BacktrackableArray::new(&mut builder, vec!['r', 'u', 't', 's']);
-    /// let mut trail = builder.finish();
-    ///
-    /// assert_eq!(array.get(&trail, 2), 't');
-    /// assert_eq!(array.get(&trail, 3), 's');
-    ///
-    /// array.swap(&mut trail, 2, 3);
-    ///
-    /// assert_eq!(array.get(&trail, 2), 's');
-    /// assert_eq!(array.get(&trail, 3), 't');
-    /// ```
-    #[inline]
-    pub fn swap(&self, trail: &mut Trail, i: usize, j: usize) {
-        self.pointer.swap(M::memory_mut(trail), i, j);
-    }
-}
+        #[test]
+        fn watch() {
+            let mut builder = TrailBuilder::new();
+            let value = BacktrackableValue::new(&mut builder, 0u32);
+            let array = NonBacktrackableArray::new(&mut builder, vec![0u8; 3]);
+            let mut trail = builder.finish();
 
-impl<M, T> Clone for Array<M, T> {
-    fn clone(&self) -> Self {
-        Self {
-            pointer: self.pointer,
-            phantom: PhantomData,
+            let on_write = value.watch(&mut trail, EventKind::Write);
+            let on_change = value.watch(&mut trail, EventKind::Change);
+            let element = array.watch(&mut trail, 2, EventKind::Change);
+            let fired = |trail: &mut Trail| {
+                trail
+                    .drain_events()
+                    .map(|event| event.watch)
+                    .collect::<Vec<_>>()
+            };
+
+            trail.new_level();
+            value.update(&mut trail, |x| x + 1);
+            value.set(&mut trail, 1);
+            array.swap(&mut trail, 0, 2);
+            array.update(&mut trail, 2, |x| x + 1);
+            assert_eq!(
+                fired(&mut trail),
+                vec![on_write, on_change, on_write, element]
+            );
+
+            // backtracking restores the value without adding events
+            value.set(&mut trail, 2);
+            trail.backtrack();
+            assert_eq!(value.get(&trail), 0);
+            assert_eq!(fired(&mut trail), vec![on_write, on_change]);
+
+            assert!(trail.unwatch(on_write));
+            value.set(&mut trail, 3);
+            assert_eq!(fired(&mut trail), vec![on_change]);
         }
-    }
-}
 
-impl<M, T> Copy for Array<M, T> {}
+        #[test]
+        fn watch_freed() {
+            let mut trail = TrailBuilder::new().finish();
+            trail.new_level();
+            let freed = BacktrackableValue::alloc(&mut trail, 0u32);
+            freed.watch(&mut trail, EventKind::Write);
+            trail.backtrack();
 
-impl<M, T> fmt::Debug for Array<M, T>
-where
-    M: StorageMode,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("Array")
-            .field("pointer", &self.pointer)
-            .field("storage_mode", &M::default())
-            .finish()
-    }
-}
+            trail.new_level();
+            let reused = BacktrackableValue::alloc(&mut trail, 0u32);
+            assert_eq!(reused.byte_range(), freed.byte_range());
+            reused.set(&mut trail, 1);
+            assert_eq!(trail.next_event(), None);
 
-impl<M, T> Eq for Array<M, T> {}
+            let array = BacktrackableArray::alloc(&mut trail, vec![0u32; 2]);
+            let watch = array.watch(&mut trail, 1, EventKind::Write);
+            array.fill(&mut trail, 1);
+            assert_eq!(trail.next_event().unwrap().watch, watch);
+        }
 
-impl<M, T> PartialEq for Array<M, T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.pointer == other.pointer
-    }
-}
+        #[test]
+        fn constant() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let table = ConstantArray::new(&mut builder, vec![5u64; 100]);
+                let value = BacktrackableValue::new(&mut builder, 0u64);
+                let mut trail = builder.finish();
 
-/// An iterator over the values of an `Array`.
-pub struct ArrayIter<'t, M, T> {
-    trail: &'t Trail,
-    index: usize,
-    array: Array<M, T>,
-}
+                trail.new_level();
+                let last = table.get(&trail, 99);
+                value.set(&mut trail, last);
+                assert_eq!(
+                    trail.stats().snapshot_bytes,
+                    trail.backtrackable_mem.len() * { (strategy == Strategy::Copy) as usize }
+                );
+                trail.backtrack();
+                assert_eq!(value.get(&trail), 0);
+                assert_eq!(table.iter(&trail).sum::<u64>(), 500);
+            }
+        }
 
-impl<'t, M, T> Iterator for ArrayIter<'t, M, T>
-where
-    M: StorageMode,
-    T: Bytes,
-{
-    type Item = T;
+        #[test]
+        fn fork() {
+            let mut builder = TrailBuilder::new();
+            let table = ConstantArray::new(&mut builder, 0..10u8);
+            let value = NonBacktrackableValue::new(&mut builder, 1u8);
+            let mut trail = builder.finish();
+            value.watch(&mut trail, EventKind::Write);
 
-    fn next(&mut self) -> Option<T> {
-        if self.index == self.array.len() {
-            None
-        } else {
-            let to_ret = Some(self.array.get(self.trail, self.index));
-            self.index += 1;
-            to_ret
+            let mut fork = trail.fork();
+            assert!(Arc::ptr_eq(&trail.constant_mem, &fork.constant_mem));
+
+            let last = table.get(&fork, 9);
+            value.set(&mut fork, last);
+            assert_eq!(value.get(&trail), 1);
+            assert_eq!(value.get(&fork), 9);
+            assert_eq!(trail.next_event(), None);
+            assert!(fork.next_event().is_some());
+
+            // extending a fork stops sharing its constant memory
+            let more = fork.extend(|builder| ConstantValue::new(builder, 3u8));
+            assert!(!Arc::ptr_eq(&trail.constant_mem, &fork.constant_mem));
+            assert_eq!(more.get(&fork), 3);
+            assert_eq!(table.get(&fork, 4), 4);
+            assert_eq!(trail.constant_mem.len(), 10);
         }
-    }
-}
 
-/// A value stored on the trail in backtrackable memory.
-pub type BacktrackableValue<T> = Value<Backtrackable, T>;
+        #[test]
+        fn restart() {
+            let mut builder = TrailBuilder::new();
+            let counter = RestartValue::new(&mut builder, 10u32);
+            let value = BacktrackableValue::new(&mut builder, 0u32);
+            let mut trail = builder.finish();
+            let watch = counter.watch(&mut trail, EventKind::Write);
 
-/// A value stored on the trail in non-backtrackable memory.
-pub type NonBacktrackableValue<T> = Value<NonBacktrackable, T>;
+            counter.set(&mut trail, 11);
+            let extra = trail.extend(|builder| RestartArray::new(builder, vec![1u8, 2]));
+            extra.set(&mut trail, 0, 3);
 
-/// A fixed-length array stored on the trail in backtrackable memory.
-pub type BacktrackableArray<T> = Array<Backtrackable, T>;
+            trail.new_level();
+            value.set(&mut trail, 1);
+            counter.update(&mut trail, |x| x + 1);
+            trail.new_level();
+            trail.backtrack();
+            assert_eq!(counter.get(&trail), 12);
+            trail.drain_events();
 
-/// A fixed-length array stored on the trail in non-backtrackable memory.
-pub type NonBacktrackableArray<T> = Array<NonBacktrackable, T>;
+            trail.restart();
+            assert!(trail.is_trail_empty());
+            assert_eq!(value.get(&trail), 0);
+            assert_eq!(counter.get(&trail), 10);
+            assert_eq!(extra.iter(&trail).collect::<Vec<_>>(), vec![1, 2]);
+            assert_eq!(trail.next_event(), None);
+            assert_eq!(trail.stats().restarts, 1);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+            counter.set(&mut trail, 20);
+            assert_eq!(trail.next_event().unwrap().watch, watch);
+            trail.restart();
+            assert_eq!(counter.get(&trail), 10);
+            assert_eq!(trail.stats().restarts, 2);
+        }
+
+        #[test]
+        #[should_panic(expected = "only a trail with no levels can be forked")]
+        fn fork_with_levels() {
+            let mut trail = TrailBuilder::new().finish();
+            trail.new_level();
+            trail.fork();
+        }
+    }
 
     mod value {
         use super::*;
@@ -761,6 +3932,21 @@ mod tests {
             assert_eq!(non_backtrackable.get(&trail), new_val);
         }
 
+        #[test]
+        #[cfg(any(debug_assertions, feature = "checked"))]
+        #[should_panic(
+            expected = "pointer used with memory other than the memory it was created for"
+        )]
+        fn other_trail() {
+            let mut builder = TrailBuilder::new();
+            let value = BacktrackableValue::new(&mut builder, 0);
+            let mut other = TrailBuilder::new();
+            BacktrackableValue::new(&mut other, 0);
+            let mut other = other.finish();
+
+            value.set(&mut other, 1);
+        }
+
         #[test]
         fn update() {
             let mut builder = TrailBuilder::new();
@@ -893,5 +4079,69 @@ mod tests {
             assert_eq!(array.get(&mut trail, 0), 1);
             assert_eq!(array.get(&mut trail, 1), -1);
         }
+
+        #[test]
+        fn bulk() {
+            for &strategy in &all_strategies() {
+                let mut builder = TrailBuilder::with_strategy(strategy);
+                let array = BacktrackableArray::new(&mut builder, 0..8u64);
+                let mut trail = builder.finish();
+
+                trail.new_level();
+                array.write_from(&mut trail, &[7, 6, 5, 4, 3, 2, 1, 0]);
+                trail.new_level();
+                array.slice(..4).fill(&mut trail, 1);
+                array.copy_within(&mut trail, 4..7, 5);
+                array.slice(6..).for_each_mut(&mut trail, |x| *x *= 10);
+                assert_eq!(array.as_slice(&trail), &[1, 1, 1, 1, 3, 3, 20, 10]);
+
+                trail.backtrack();
+                assert_eq!(array.as_slice(&trail), &[7, 6, 5, 4, 3, 2, 1, 0]);
+
+                trail.backtrack();
+                let mut out = [0; 8];
+                array.read_into(&trail, &mut out);
+                assert_eq!(out, [0, 1, 2, 3, 4, 5, 6, 7]);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "destination range out of bounds")]
+        fn copy_within_overflow() {
+            let mut builder = TrailBuilder::new();
+            let array = BacktrackableArray::new(&mut builder, 0..8u64);
+            let mut trail = builder.finish();
+
+            array.copy_within(&mut trail, 4.., usize::MAX - 1);
+        }
+
+        #[test]
+        fn bulk_watch() {
+            let mut builder = TrailBuilder::new();
+            let array = NonBacktrackableArray::new(&mut builder, vec![0u16; 6]);
+            let mut trail = builder.finish();
+
+            let first = array.watch(&mut trail, 0, EventKind::Change);
+            let third = array.watch(&mut trail, 2, EventKind::Write);
+            let last = array.watch(&mut trail, 5, EventKind::Change);
+            let fired = |trail: &mut Trail| {
+                trail
+                    .drain_events()
+                    .map(|event| event.watch)
+                    .collect::<Vec<_>>()
+            };
+
+            array.slice(1..).fill(&mut trail, 0);
+            assert_eq!(fired(&mut trail), vec![third]);
+
+            array.write_from(&mut trail, &[0, 1, 2, 3, 4, 5]);
+            assert_eq!(fired(&mut trail), vec![third, last]);
+
+            array.copy_within(&mut trail, 1..3, 0);
+            assert_eq!(fired(&mut trail), vec![first]);
+
+            array.for_each_mut(&mut trail, |x| *x = x.saturating_sub(4));
+            assert_eq!(fired(&mut trail), vec![first, third, last]);
+        }
     }
 }
@@ -11,7 +11,161 @@
 //! `Pointer` and `ArrayPointer` are only usable with the `Memory` from the finished
 //! `MemoryBuilder` used to create the pointer (or a clone of the `Memory`).  It is unsafe behavior
 //! to use a pointer with any `Memory` other than what initialized it.
-use std::{fmt, marker::PhantomData};
+//!
+//! In debug builds, or when the `checked` feature is enabled, every `MemoryBuilder` is given a
+//! unique identifier that is shared by its pointers and its `Memory`, and using a pointer with any
+//! other `Memory` panics instead.
+//!
+//! # Alignment
+//!
+//! Every value is stored at an offset that's a multiple of the alignment of its type, up to
+//! [`MAX_ALIGN`](MAX_ALIGN) bytes, and the bytes of a `Memory` start at an address aligned to
+//! `MAX_ALIGN`. Values are read and written in place with
+//! [`Bytes::read_aligned`](Bytes::read_aligned) and [`Bytes::write_aligned`](Bytes::write_aligned),
+//! and arrays of [`Plain`](Plain) types can be borrowed as slices with
+//! [`ArrayPointer::as_slice`](ArrayPointer::as_slice).
+use crate::journal::Journal;
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem,
+    num::NonZeroU64,
+    ops::{Bound, Deref, DerefMut, Range, RangeBounds},
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The identifier given to the next `MemoryBuilder`.
+#[cfg(any(debug_assertions, feature = "checked"))]
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// An identifier shared by a `MemoryBuilder`, the `Memory` it creates, and its pointers.
+#[cfg(any(debug_assertions, feature = "checked"))]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct MemoryId(u64);
+
+/// An identifier shared by a `MemoryBuilder`, the `Memory` it creates, and its pointers.
+///
+/// Identifiers are only checked in debug builds or when the `checked` feature is enabled, so this
+/// is zero-sized otherwise.
+#[cfg(not(any(debug_assertions, feature = "checked")))]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct MemoryId;
+
+impl MemoryId {
+    /// Creates a new identifier, distinct from every identifier created before.
+    #[cfg(any(debug_assertions, feature = "checked"))]
+    fn new() -> Self {
+        MemoryId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    #[cfg(not(any(debug_assertions, feature = "checked")))]
+    fn new() -> Self {
+        MemoryId
+    }
+
+    /// Returns the identifier as a `u64`, which is always 0 if identifiers aren't checked.
+    #[cfg(feature = "serde")]
+    fn to_u64(self) -> u64 {
+        #[cfg(any(debug_assertions, feature = "checked"))]
+        return self.0;
+        #[cfg(not(any(debug_assertions, feature = "checked")))]
+        return 0;
+    }
+
+    /// Recreates an identifier from [`to_u64`](MemoryId::to_u64), making sure that identifiers
+    /// created later are distinct from it.
+    #[cfg(feature = "serde")]
+    fn from_u64(id: u64) -> Self {
+        #[cfg(any(debug_assertions, feature = "checked"))]
+        {
+            NEXT_ID.fetch_max(id.saturating_add(1), Ordering::Relaxed);
+            MemoryId(id)
+        }
+        #[cfg(not(any(debug_assertions, feature = "checked")))]
+        {
+            let _ = id;
+            MemoryId
+        }
+    }
+
+    /// Panics if a pointer with this identifier is used with memory with a different identifier.
+    #[inline]
+    fn check(self, memory: &Memory) {
+        assert!(
+            self == memory.id,
+            "pointer used with memory other than the memory it was created for"
+        );
+    }
+}
+
+// identifiers are serialized in every build, so that memory serialized by a debug build can be
+// deserialized by a release build and vice versa
+#[cfg(feature = "serde")]
+impl Serialize for MemoryId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.to_u64())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for MemoryId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(MemoryId::from_u64)
+    }
+}
+
+/// The generation given to the next allocation.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// An identifier of a value or array allocated in a finished `Memory`, distinct from every
+/// allocation made before in any memory.
+///
+/// Pointers to allocations carry their generation, so that using a pointer after its allocation
+/// was freed panics even if the bytes were since reused by another allocation.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) struct Generation(NonZeroU64);
+
+impl Generation {
+    /// Creates a new generation, distinct from every generation created before.
+    fn new() -> Self {
+        Generation(NonZeroU64::new(NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)).unwrap())
+    }
+
+    /// Creates a generation from a number returned by [`to_u64`](Generation::to_u64), or returns
+    /// `None` if the number is zero.
+    ///
+    /// Generations created after this one are distinct from it, so that a loaded pointer never
+    /// matches an allocation it doesn't point to.
+    pub(crate) fn from_u64(generation: u64) -> Option<Self> {
+        let generation = NonZeroU64::new(generation)?;
+        NEXT_GENERATION.fetch_max(generation.get().saturating_add(1), Ordering::Relaxed);
+        Some(Generation(generation))
+    }
+
+    /// Returns the generation as a number.
+    pub(crate) fn to_u64(self) -> u64 {
+        self.0.get()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Generation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Generation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let generation = NonZeroU64::deserialize(deserializer)?;
+        Ok(Generation::from_u64(generation.get()).unwrap())
+    }
+}
 
 /// Anything that can be converted to or from a fixed-length byte slice.
 ///
@@ -100,6 +254,8 @@ pub trait Bytes: Copy + 'static {
 
     /// Reads a value of type `Self` from the byte slice.
     ///
+    /// # Safety
+    ///
     /// The caller must guarantee that `bytes.len() == Self::LENGTH` and that the byte slice
     /// represents a valid value of type `Self`. Really the only way to be sure of this is to write
     /// a valid value to the byte slice beforehand.
@@ -107,23 +263,558 @@ pub trait Bytes: Copy + 'static {
 
     /// Writes a copy of `self` to the byte slice.
     ///
+    /// # Safety
+    ///
     /// The caller must guarantee that `bytes.len() == Self::LENGTH`.
     unsafe fn write_bytes(self, bytes: &mut [u8]);
+
+    /// Reads a value of type `Self` from a byte slice that's aligned for `Self`.
+    ///
+    /// This is how values are read from `Memory`. The default implementation calls
+    /// [`read_bytes`](Bytes::read_bytes), and implementations for primitive types and derived
+    /// implementations read the value with a single aligned load instead.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee everything required by [`read_bytes`](Bytes::read_bytes), and
+    /// that the address of the byte slice is a multiple of the alignment of `Self`.
+    #[inline]
+    unsafe fn read_aligned(bytes: &[u8]) -> Self {
+        Self::read_bytes(bytes)
+    }
+
+    /// Writes a copy of `self` to a byte slice that's aligned for `Self`.
+    ///
+    /// This is how values are written to `Memory`. The default implementation calls
+    /// [`write_bytes`](Bytes::write_bytes).
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that `bytes.len() == Self::LENGTH` and that the address of the
+    /// byte slice is a multiple of the alignment of `Self`.
+    #[inline]
+    unsafe fn write_aligned(self, bytes: &mut [u8]) {
+        self.write_bytes(bytes)
+    }
+}
+
+/// A type whose bytes are stored in memory exactly as the value itself is, so that values in
+/// memory can be borrowed in place.
+///
+/// `Plain` is implemented for every primitive type that implements [`Bytes`](Bytes), and it's
+/// implemented by `#[derive(Bytes)]` as well.
+///
+/// # Safety
+///
+/// `Self::LENGTH` must be the size of `Self`, and [`write_bytes`](Bytes::write_bytes) must write
+/// the in-memory representation of the value, so that the written bytes can be read as a `Self`
+/// through a pointer.
+pub unsafe trait Plain: Bytes {}
+
+/// The largest alignment of values in memory, in bytes.
+///
+/// Values of types with a larger alignment are stored at offsets that are multiples of
+/// `MAX_ALIGN` instead, and are read and written with [`Bytes::read_bytes`](Bytes::read_bytes) and
+/// [`Bytes::write_bytes`](Bytes::write_bytes).
+pub const MAX_ALIGN: usize = 16;
+
+/// Returns the alignment used to store values of type `T` in memory.
+#[inline]
+const fn align_of<T>() -> usize {
+    if mem::align_of::<T>() < MAX_ALIGN {
+        mem::align_of::<T>()
+    } else {
+        MAX_ALIGN
+    }
+}
+
+/// Rounds the offset up to a multiple of the alignment used to store values of type `T`.
+#[inline]
+fn pad<T>(offset: usize) -> usize {
+    let align = align_of::<T>();
+    (offset + align - 1) & !(align - 1)
+}
+
+/// Checks if the bytes at the given address can be read and written as a `T` in place.
+///
+/// Values are stored at offsets padded for `T`, but elements of an array of a type whose
+/// `LENGTH` isn't a multiple of its alignment aren't aligned, and neither are values of types
+/// aligned to more than `MAX_ALIGN` bytes.
+#[inline]
+fn is_aligned<T>(ptr: *const u8) -> bool {
+    mem::align_of::<T>() <= MAX_ALIGN && ptr as usize & (mem::align_of::<T>() - 1) == 0
+}
+
+/// Reads a value of type `T` from bytes of memory, with an aligned load if the bytes are aligned
+/// for `T`.
+///
+/// # Safety
+///
+/// Same as [`Bytes::read_bytes`](Bytes::read_bytes).
+#[inline]
+unsafe fn read_in_place<T: Bytes>(bytes: &[u8]) -> T {
+    if is_aligned::<T>(bytes.as_ptr()) {
+        T::read_aligned(bytes)
+    } else {
+        T::read_bytes(bytes)
+    }
+}
+
+/// Writes a value of type `T` to bytes of memory, with an aligned store if the bytes are aligned
+/// for `T`.
+///
+/// # Safety
+///
+/// Same as [`Bytes::write_bytes`](Bytes::write_bytes).
+#[inline]
+unsafe fn write_in_place<T: Bytes>(val: T, bytes: &mut [u8]) {
+    if is_aligned::<T>(bytes.as_ptr()) {
+        val.write_aligned(bytes)
+    } else {
+        val.write_bytes(bytes)
+    }
+}
+
+/// Converts a range of indices of a sequence of length `len` to a `Range`.
+///
+/// # Panics
+///
+/// Panics if the range is decreasing or out of bounds.
+pub(crate) fn to_range(range: impl RangeBounds<usize>, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.checked_add(1).expect("range out of bounds"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.checked_add(1).expect("range out of bounds"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(start <= end && end <= len, "range out of bounds");
+    start..end
+}
+
+/// A chunk of bytes aligned to `MAX_ALIGN`.
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct Chunk([u8; MAX_ALIGN]);
+
+/// A growable buffer of bytes whose first byte is aligned to `MAX_ALIGN`.
+#[derive(Clone, Default)]
+struct AlignedBytes {
+    chunks: Vec<Chunk>,
+    len: usize,
+}
+
+impl AlignedBytes {
+    /// Resizes the buffer to `len` bytes, filling any new bytes with zeros.
+    fn resize(&mut self, len: usize) {
+        self.chunks
+            .resize(len.div_ceil(MAX_ALIGN), Chunk([0; MAX_ALIGN]));
+        let old_len = self.len;
+        self.len = len;
+        if old_len < len {
+            self[old_len..].iter_mut().for_each(|byte| *byte = 0);
+        }
+    }
+}
+
+impl From<&[u8]> for AlignedBytes {
+    fn from(bytes: &[u8]) -> Self {
+        let mut aligned = AlignedBytes::default();
+        aligned.resize(bytes.len());
+        aligned.copy_from_slice(bytes);
+        aligned
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        // safe because the chunks hold at least `len` initialized bytes
+        unsafe { slice::from_raw_parts(self.chunks.as_ptr() as *const u8, self.len) }
+    }
+}
+
+impl DerefMut for AlignedBytes {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // safe because the chunks hold at least `len` initialized bytes
+        unsafe { slice::from_raw_parts_mut(self.chunks.as_mut_ptr() as *mut u8, self.len) }
+    }
+}
+
+impl fmt::Debug for AlignedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self[..], f)
+    }
 }
 
 /// A fixed-size chunk of bytes that can be accessed and updated using pointers.
 ///
-/// `Memory` has no methods itself.  To create `Memory`, use a
-/// [`MemoryBuilder`](crate::mem::MemoryBuilder).  All operations that read from or write to the
-/// memory are performed with a [`Pointer`](Pointer) or an [`ArrayPointer`](ArrayPointer).
+/// To create `Memory`, use a [`MemoryBuilder`](crate::mem::MemoryBuilder).  All operations that
+/// read from or write to individual values in the memory are performed with a
+/// [`Pointer`](Pointer) or an [`ArrayPointer`](ArrayPointer).
 ///
 /// # Warning
 ///
 /// It is unsafe behavior to use pointers with `Memory` other than the `Memory` from the
 /// `MemoryBuilder` used to initialize the pointer.
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// Deserialized memory is trusted to hold valid values wherever its pointers point, so only
+/// deserialize memory from a trusted source. See
+/// [trusted input](crate::checkpoint#trusted-input).
+#[derive(Clone, Debug)]
 pub struct Memory {
-    bytes: Vec<u8>,
+    id: MemoryId,
+    /// The bytes of the memory, followed by the bytes of any allocations that have since been
+    /// freed. Freed bytes are kept so that stale pointers still point into the buffer.
+    bytes: AlignedBytes,
+    /// The number of bytes in use.
+    len: usize,
+    /// The number of bytes added by the `MemoryBuilder`, including the bytes appended later
+    /// with [`append`](Memory::append).
+    layout_len: usize,
+    /// The end offset and generation of every allocation that hasn't been freed, oldest first.
+    allocations: Vec<(usize, Generation)>,
+    journal: Journal,
+    written: usize,
+    /// The number of writes since the counter was last reset.
+    writes: usize,
+}
+
+impl Memory {
+    /// Returns the bytes in the given range.
+    #[inline]
+    fn bytes(&self, offset: usize, len: usize) -> &[u8] {
+        self.check_bounds(offset, len);
+        unsafe { self.bytes.get_unchecked(offset..(offset + len)) }
+    }
+
+    /// Returns the bytes in the given range mutably, recording their current contents in the
+    /// journal first if the journal is recording.
+    #[inline]
+    fn bytes_mut(&mut self, offset: usize, len: usize) -> &mut [u8] {
+        self.check_bounds(offset, len);
+        self.written = self.written.wrapping_add(len);
+        self.writes = self.writes.wrapping_add(1);
+        if self.journal.is_recording() {
+            self.journal.record(&self.bytes, offset, len);
+        }
+        unsafe { self.bytes.get_unchecked_mut(offset..(offset + len)) }
+    }
+
+    /// Copies the bytes in the given range to the bytes starting at `dest`, recording the bytes
+    /// they overwrite in the journal first if the journal is recording.
+    fn copy_within(&mut self, src: Range<usize>, dest: usize) {
+        self.check_bounds(src.start, src.len());
+        self.bytes_mut(dest, src.len());
+        self.bytes.copy_within(src, dest);
+    }
+
+    /// Panics if the allocation with the given generation has been freed.
+    #[inline]
+    fn check_allocation(&self, generation: Option<Generation>) {
+        if let Some(generation) = generation {
+            assert!(
+                has_allocation(&self.allocations, generation),
+                "pointer used after the memory it points to was freed"
+            );
+        }
+    }
+
+    /// Panics if the given range isn't in use.
+    ///
+    /// Pointers created by a builder or allocated in the memory are always in bounds, but a
+    /// deserialized pointer can hold any offset, so the bounds are checked in every build.
+    #[inline]
+    fn check_bounds(&self, offset: usize, len: usize) {
+        assert!(
+            len <= self.len && offset <= self.len - len,
+            "pointer out of bounds of the memory it was used with"
+        );
+    }
+
+    /// Returns the total number of bytes in the memory.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of bytes added by the `MemoryBuilder`, including the bytes appended
+    /// later with [`append`](Memory::append).
+    pub(crate) fn layout_len(&self) -> usize {
+        self.layout_len
+    }
+
+    /// Returns the number of bytes written to the memory so far, wrapping on overflow.
+    pub(crate) fn bytes_written(&self) -> usize {
+        self.written
+    }
+
+    /// Returns the number of writes to the memory since the counter was last reset, wrapping on
+    /// overflow.
+    pub(crate) fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// Resets the write counter to zero.
+    pub(crate) fn reset_writes(&mut self) {
+        self.writes = 0;
+    }
+
+    /// Returns the journal of the memory.
+    pub(crate) fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Returns the journal of the memory.
+    pub(crate) fn journal_mut(&mut self) -> &mut Journal {
+        &mut self.journal
+    }
+
+    /// Returns a copy of the bytes of the memory.
+    pub(crate) fn snapshot(&self) -> Vec<u8> {
+        self.bytes[..self.len].to_vec()
+    }
+
+    /// Replaces the bytes of the memory with a snapshot, discarding every journal entry after the
+    /// given mark.
+    ///
+    /// Any bytes allocated since the snapshot was taken are freed.
+    pub(crate) fn restore(&mut self, snapshot: Vec<u8>, mark: usize) {
+        self.bytes[..snapshot.len()].copy_from_slice(&snapshot);
+        self.len = snapshot.len();
+        self.free_allocations();
+        self.journal.truncate(mark);
+    }
+
+    /// Undoes every write recorded in the journal after the given mark.
+    pub(crate) fn undo(&mut self, mark: usize) {
+        self.journal.undo(&mut self.bytes, mark);
+    }
+
+    /// Returns a `MemoryBuilder` with the same identifier and a copy of the bytes of the memory,
+    /// so that more values can be added to the end of the memory with [`append`](Memory::append).
+    pub(crate) fn to_builder(&self) -> MemoryBuilder {
+        MemoryBuilder {
+            id: self.id,
+            bytes: self.bytes[..self.len].to_vec(),
+        }
+    }
+
+    /// Adds the bytes added to a builder created by [`to_builder`](Memory::to_builder) to the end
+    /// of the memory.
+    pub(crate) fn append(&mut self, builder: &MemoryBuilder) {
+        debug_assert_eq!(self.id, builder.id);
+        let start = self.len;
+        let bytes = &builder.bytes[start..];
+        self.alloc(bytes.len()).copy_from_slice(bytes);
+        self.layout_len = self.len;
+    }
+
+    /// Adds `len` bytes to the end of the memory and returns them.
+    ///
+    /// Allocating doesn't count as writing to the memory, and is never recorded in the journal.
+    pub(crate) fn alloc(&mut self, len: usize) -> &mut [u8] {
+        let offset = self.len;
+        self.len += len;
+        if self.bytes.len() < self.len {
+            self.bytes.resize(self.len);
+        }
+        &mut self.bytes[offset..self.len]
+    }
+
+    /// Records an allocation that ends at the given offset and returns its generation.
+    fn track(&mut self, end: usize) -> Generation {
+        let generation = Generation::new();
+        self.allocations.push((end, generation));
+        generation
+    }
+
+    /// Returns the end offset and generation of every allocation that hasn't been freed, oldest
+    /// first.
+    pub(crate) fn allocations(&self) -> &[(usize, Generation)] {
+        &self.allocations
+    }
+
+    /// Replaces the allocations that haven't been freed, freeing any that end past the bytes in
+    /// use.
+    pub(crate) fn set_allocations(&mut self, allocations: Vec<(usize, Generation)>) {
+        self.allocations = allocations;
+        self.free_allocations();
+    }
+
+    /// Frees every allocation that ends past the bytes in use.
+    fn free_allocations(&mut self) {
+        while let Some(&(end, _)) = self.allocations.last() {
+            if end <= self.len {
+                break;
+            }
+            self.allocations.pop();
+        }
+    }
+
+    /// Adds bytes to the end of the memory until its length is a multiple of the alignment used
+    /// to store values of type `T`.
+    fn pad<T>(&mut self) {
+        self.alloc(pad::<T>(self.len) - self.len);
+    }
+
+    /// Returns a copy of the bytes in the given range as they were before the writes recorded in
+    /// the journal after `mark`.
+    ///
+    /// If `snapshot` is given, it's a copy of the memory taken when the journal's length was the
+    /// given mark, and it's used in place of the current bytes and the entries recorded after it.
+    pub(crate) fn bytes_at(
+        &self,
+        offset: usize,
+        len: usize,
+        mark: usize,
+        snapshot: Option<(&[u8], usize)>,
+    ) -> Vec<u8> {
+        let (bytes, end) = snapshot.unwrap_or((&self.bytes[..], self.journal.mark()));
+        let mut window = bytes[offset..(offset + len)].to_vec();
+        self.journal.undo_window(&mut window, offset, mark..end);
+        window
+    }
+
+    /// Replaces the bytes in use, the allocations, the journal and the write counter of the
+    /// memory.
+    pub(crate) fn set_state(
+        &mut self,
+        bytes: Vec<u8>,
+        allocations: Vec<(usize, Generation)>,
+        journal: Journal,
+        written: usize,
+    ) {
+        self.len = bytes.len();
+        self.bytes = AlignedBytes::from(&bytes[..]);
+        self.allocations = allocations;
+        self.journal = journal;
+        self.written = written;
+    }
+
+    /// Grows the buffer holding the bytes in use and any freed bytes after them to at least
+    /// `capacity` bytes.
+    ///
+    /// A loaded memory may have been longer when an undo log entry was recorded or a level was
+    /// added, if a shorter snapshot was restored since, and the buffer needs room for undoing
+    /// that.
+    pub(crate) fn reserve(&mut self, capacity: usize) {
+        if self.bytes.len() < capacity {
+            self.bytes.resize(capacity);
+        }
+    }
+
+    /// Returns the bytes of the memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{MemoryBuilder, Pointer};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let pointer = Pointer::new(&mut builder, 0x0102u16);
+    /// let memory = builder.finish();
+    ///
+    /// assert_eq!(memory.as_slice(), &0x0102u16.to_ne_bytes());
+    /// ```
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Replaces every byte of the memory with the given bytes.
+    ///
+    /// This is mostly useful to implement a [`RestorePolicy`](crate::storage::RestorePolicy)
+    /// that saves copies of the memory.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that the bytes represent a valid value at every pointer that's
+    /// used with the memory afterwards. Really the only way to be sure of this is to pass a copy
+    /// of the same memory taken earlier with [`as_slice`](Memory::as_slice), when it had the same
+    /// length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bytes is different from the length of the memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{MemoryBuilder, Pointer};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let pointer = Pointer::new(&mut builder, 'a');
+    /// let mut memory = builder.finish();
+    ///
+    /// let saved = memory.as_slice().to_vec();
+    /// pointer.set(&mut memory, 'b');
+    /// // SAFETY: the bytes are a copy of the same memory
+    /// unsafe { memory.copy_from_slice(&saved) };
+    /// assert_eq!(pointer.get(&memory), 'a');
+    /// ```
+    pub unsafe fn copy_from_slice(&mut self, bytes: &[u8]) {
+        assert_eq!(
+            bytes.len(),
+            self.len,
+            "the number of bytes doesn't match the length of the memory"
+        );
+        self.overwrite(Some(bytes));
+    }
+
+    /// Replaces the bytes in use with the given chunks of bytes, recording the current bytes in
+    /// the journal first if the journal is recording.
+    pub(crate) fn overwrite<'a>(&mut self, chunks: impl IntoIterator<Item = &'a [u8]>) {
+        if self.journal.is_recording() {
+            self.journal.record_snapshot(&self.bytes[..self.len]);
+        }
+        self.len = 0;
+        for chunk in chunks {
+            self.alloc(chunk.len()).copy_from_slice(chunk);
+        }
+        self.free_allocations();
+        self.written = self.written.wrapping_add(self.len);
+    }
+
+    /// Sets the number of bytes in use to `len`, freeing the allocations past it.
+    ///
+    /// The length is usually smaller than the current length, but it can also be larger after
+    /// undoing an [`overwrite`](Memory::overwrite) of the memory with fewer bytes: the undo has
+    /// already brought back the bytes that were in use, so only the length is left to restore.
+    pub(crate) fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.bytes.len());
+        self.len = len;
+        self.free_allocations();
+    }
+}
+
+impl Eq for Memory {}
+
+/// Memory is equal if the bytes in use are equal, whatever the writes that produced them.
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+/// Checks if a list of allocations read from a saved memory is sorted by end offset and by
+/// generation, like the lists kept by `Memory`.
+pub(crate) fn is_allocation_list(allocations: &[(usize, Generation)]) -> bool {
+    allocations
+        .windows(2)
+        .all(|pair| pair[0].0 <= pair[1].0 && pair[0].1 < pair[1].1)
+}
+
+/// Checks if the allocation with the given generation is in a list of allocations sorted by
+/// generation.
+pub(crate) fn has_allocation(allocations: &[(usize, Generation)], generation: Generation) -> bool {
+    allocations
+        .binary_search_by_key(&generation, |&(_, generation)| generation)
+        .is_ok()
 }
 
 /// A growable chunk of bytes that can be built into `Memory`.
@@ -139,6 +830,10 @@ pub struct Memory {
 /// It is unsafe behavior to use pointers with `Memory` other than the `Memory` from the
 /// `MemoryBuilder` used to initialize the pointer.
 ///
+/// Deserialized memory is trusted to hold valid values wherever its pointers point, so only
+/// deserialize memory from a trusted source. See
+/// [trusted input](crate::checkpoint#trusted-input).
+///
 /// # Examples
 ///
 /// ```
@@ -151,8 +846,9 @@ pub struct Memory {
 /// // the pointer is usable with the memory after the builder finishes
 /// assert_eq!(pointer.get(&memory), 'R');
 /// ```
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq)]
 pub struct MemoryBuilder {
+    id: MemoryId,
     bytes: Vec<u8>,
 }
 
@@ -167,7 +863,10 @@ impl MemoryBuilder {
     /// let mut builder = MemoryBuilder::new();
     /// ```
     pub fn new() -> Self {
-        Self { bytes: vec![] }
+        Self {
+            id: MemoryId::new(),
+            bytes: vec![],
+        }
     }
 
     /// Consumes the `MemoryBuilder` to create a `Memory`.
@@ -190,7 +889,22 @@ impl MemoryBuilder {
     /// assert_eq!(array.get(&memory, 5), 5);
     /// ```
     pub fn finish(self) -> Memory {
-        Memory { bytes: self.bytes }
+        Memory {
+            id: self.id,
+            len: self.bytes.len(),
+            layout_len: self.bytes.len(),
+            bytes: AlignedBytes::from(&self.bytes[..]),
+            allocations: vec![],
+            journal: Journal::default(),
+            written: 0,
+            writes: 0,
+        }
+    }
+}
+
+impl Default for MemoryBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -199,7 +913,14 @@ impl MemoryBuilder {
 /// # Warning
 ///
 /// A `Pointer` is only usable with the `Memory` from the `MemoryBuilder` used to create the
-/// `Pointer`. Using a `Pointer` with any other memory is considered undefined behavior.
+/// `Pointer`. Using a `Pointer` with any other memory is considered undefined behavior, and panics
+/// in debug builds or when the `checked` feature is enabled.
+/// A pointer that reaches past the end of the memory it's used with, such as a malformed
+/// deserialized pointer, panics in every build.
+///
+/// A serialized pointer records the name and size of `T`, and deserializing it as a pointer to
+/// any other type fails. Beyond that, a deserialized pointer is trusted to point to a valid `T`
+/// in the memory it's used with, so only deserialize pointers and memory from a trusted source.
 ///
 /// # Examples
 ///
@@ -216,7 +937,9 @@ impl MemoryBuilder {
 /// assert_eq!(pointer.get(&memory), 20);
 /// ```
 pub struct Pointer<T> {
+    id: MemoryId,
     offset: usize,
+    generation: Option<Generation>,
     phantom: PhantomData<T>,
 }
 
@@ -241,10 +964,10 @@ where
     /// assert_eq!(pointer.get(&memory), 'b');
     /// ```
     pub fn new(builder: &mut MemoryBuilder, val: T) -> Self {
-        let offset = builder.bytes.len();
+        let offset = pad::<T>(builder.bytes.len());
 
         // create uninitialized memory
-        builder.bytes.extend((0..T::LENGTH).map(|_| 0));
+        builder.bytes.resize(offset + T::LENGTH, 0);
 
         // initialize the memory
         unsafe {
@@ -256,7 +979,22 @@ where
         }
 
         Self {
+            id: builder.id,
+            offset,
+            generation: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Allocates a new value at the end of the memory and creates a pointer to it.
+    pub(crate) fn alloc(memory: &mut Memory, val: T) -> Self {
+        memory.pad::<T>();
+        let offset = memory.len();
+        unsafe { write_in_place(val, memory.alloc(T::LENGTH)) }
+        Self {
+            id: memory.id,
             offset,
+            generation: Some(memory.track(offset + T::LENGTH)),
             phantom: PhantomData,
         }
     }
@@ -276,13 +1014,8 @@ where
     /// ```
     #[inline]
     pub fn get(self, memory: &Memory) -> T {
-        unsafe {
-            T::read_bytes(
-                memory
-                    .bytes
-                    .get_unchecked(self.offset..(self.offset + T::LENGTH)),
-            )
-        }
+        self.check(memory);
+        unsafe { read_in_place(memory.bytes(self.offset, T::LENGTH)) }
     }
 
     /// Sets the value of the pointer in memory.
@@ -301,13 +1034,8 @@ where
     /// ```
     #[inline]
     pub fn set(self, memory: &mut Memory, val: T) {
-        unsafe {
-            val.write_bytes(
-                memory
-                    .bytes
-                    .get_unchecked_mut(self.offset..(self.offset + T::LENGTH)),
-            );
-        }
+        self.check(memory);
+        unsafe { write_in_place(val, memory.bytes_mut(self.offset, T::LENGTH)) }
     }
 
     /// Updates the value in memory using the given function.
@@ -328,18 +1056,47 @@ where
     pub fn update(self, memory: &mut Memory, f: impl FnOnce(T) -> T) {
         self.set(memory, f(self.get(memory)));
     }
-}
 
-impl<T> Clone for Pointer<T> {
-    fn clone(&self) -> Self {
-        Self {
-            offset: self.offset,
-            phantom: PhantomData,
-        }
+    /// Returns the range of bytes in memory that the pointer points to.
+    pub(crate) fn range(self) -> Range<usize> {
+        self.offset..(self.offset + T::LENGTH)
     }
-}
 
-impl<T> Copy for Pointer<T> {}
+    /// Returns the generation of the allocation the pointer points to, or `None` if it wasn't
+    /// allocated.
+    pub(crate) fn generation(self) -> Option<Generation> {
+        self.generation
+    }
+
+    /// Gets the value of the pointer from the bytes returned by `bytes` for the pointer's offset,
+    /// length and generation, rather than from the current bytes of the memory.
+    ///
+    /// `bytes` must check that the allocation with the given generation, if any, hadn't been
+    /// freed at the time the bytes are from.
+    pub(crate) fn get_from(
+        self,
+        memory: &Memory,
+        bytes: impl FnOnce(usize, usize, Option<Generation>) -> Vec<u8>,
+    ) -> T {
+        self.id.check(memory);
+        unsafe { T::read_bytes(&bytes(self.offset, T::LENGTH, self.generation)) }
+    }
+
+    /// Panics if the pointer can't be used with the memory.
+    #[inline]
+    fn check(self, memory: &Memory) {
+        self.id.check(memory);
+        memory.check_allocation(self.generation);
+    }
+}
+
+impl<T> Clone for Pointer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Pointer<T> {}
 
 impl<T> fmt::Debug for Pointer<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -353,7 +1110,7 @@ impl<T> Eq for Pointer<T> {}
 
 impl<T> PartialEq for Pointer<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.offset == other.offset
+        self.id == other.id && self.offset == other.offset && self.generation == other.generation
     }
 }
 
@@ -362,7 +1119,13 @@ impl<T> PartialEq for Pointer<T> {
 /// # Warning
 ///
 /// An `ArrayPointer` is only usable with the `Memory` from the `MemoryBuilder` used to create the
-/// `ArrayPointer`. Using a `ArrayPointer` with any other memory is considered undefined behavior.
+/// `ArrayPointer`. Using a `ArrayPointer` with any other memory is considered undefined behavior,
+/// and panics in debug builds or when the `checked` feature is enabled.
+/// A pointer that reaches past the end of the memory it's used with, such as a malformed
+/// deserialized pointer, panics in every build.
+///
+/// As with [`Pointer`](Pointer), a serialized array pointer records the name and size of `T`,
+/// and a deserialized array pointer is otherwise trusted to point to valid values.
 ///
 /// # Examples
 ///
@@ -377,8 +1140,10 @@ impl<T> PartialEq for Pointer<T> {
 /// assert_eq!(array.get(&memory, 3), 2);
 /// ```
 pub struct ArrayPointer<T> {
+    id: MemoryId,
     offset: usize,
     len: usize,
+    generation: Option<Generation>,
     phantom: PhantomData<T>,
 }
 
@@ -404,12 +1169,10 @@ where
     /// assert_eq!(notes.get(&memory, 0), 'c');
     /// ```
     pub fn new(builder: &mut MemoryBuilder, vals: &[T]) -> Self {
-        let offset = builder.bytes.len();
+        let offset = pad::<T>(builder.bytes.len());
 
         // create uninitialized memory
-        builder
-            .bytes
-            .extend((0..(T::LENGTH * vals.len())).map(|_| 0));
+        builder.bytes.resize(offset + T::LENGTH * vals.len(), 0);
 
         // initialize the memory
         let mut val_offset = offset;
@@ -425,8 +1188,27 @@ where
         }
 
         Self {
+            id: builder.id,
+            offset,
+            len: vals.len(),
+            generation: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Allocates a new array of values at the end of the memory and creates a pointer to it.
+    pub(crate) fn alloc(memory: &mut Memory, vals: &[T]) -> Self {
+        memory.pad::<T>();
+        let offset = memory.len();
+        let bytes = memory.alloc(T::LENGTH * vals.len());
+        for (val, val_bytes) in vals.iter().zip(bytes.chunks_exact_mut(T::LENGTH.max(1))) {
+            unsafe { write_in_place(*val, val_bytes) }
+        }
+        Self {
+            id: memory.id,
             offset,
             len: vals.len(),
+            generation: Some(memory.track(offset + T::LENGTH * vals.len())),
             phantom: PhantomData,
         }
     }
@@ -495,14 +1277,94 @@ where
     #[inline]
     pub fn get(&self, memory: &Memory, i: usize) -> T {
         assert!(i < self.len, "array index out of bounds");
+        self.check(memory);
         let mem_offset = self.offset + i * T::LENGTH;
-        unsafe {
-            T::read_bytes(
-                memory
-                    .bytes
-                    .get_unchecked(mem_offset..(mem_offset + T::LENGTH)),
-            )
-        }
+        unsafe { read_in_place(memory.bytes(mem_offset, T::LENGTH)) }
+    }
+
+    /// Returns the values of the array pointer in memory as a slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alignment of `T` is greater than [`MAX_ALIGN`](MAX_ALIGN).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder, Pointer};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// Pointer::new(&mut builder, true);
+    /// let weights = ArrayPointer::new(&mut builder, &[1.5f64, 2.0, 0.25]);
+    /// let memory = builder.finish();
+    ///
+    /// assert_eq!(weights.as_slice(&memory), &[1.5, 2.0, 0.25]);
+    /// ```
+    #[inline]
+    pub fn as_slice<'m>(&self, memory: &'m Memory) -> &'m [T]
+    where
+        T: Plain,
+    {
+        assert!(
+            mem::align_of::<T>() <= MAX_ALIGN,
+            "values aligned to more than `MAX_ALIGN` bytes can't be borrowed in place"
+        );
+        self.check(memory);
+        let bytes = memory.bytes(self.offset, self.len * T::LENGTH);
+        assert!(
+            is_aligned::<T>(bytes.as_ptr()),
+            "array pointer isn't aligned for its values"
+        );
+        // safe because `T: Plain` and the bytes are aligned for `T`, and were written by values
+        // of type `T`
+        unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, self.len) }
+    }
+
+    /// Returns the range of bytes in memory that the array pointer points to.
+    pub(crate) fn range(&self) -> Range<usize> {
+        self.offset..(self.offset + self.len * T::LENGTH)
+    }
+
+    /// Returns the generation of the allocation the array pointer points to, or `None` if it
+    /// wasn't allocated.
+    pub(crate) fn generation(&self) -> Option<Generation> {
+        self.generation
+    }
+
+    /// Gets the value of the given index of the array pointer from the bytes returned by `bytes`
+    /// for the value's offset, length and generation, rather than from the current bytes of the
+    /// memory.
+    ///
+    /// `bytes` must check that the allocation with the given generation, if any, hadn't been
+    /// freed at the time the bytes are from.
+    pub(crate) fn get_from(
+        &self,
+        memory: &Memory,
+        i: usize,
+        bytes: impl FnOnce(usize, usize, Option<Generation>) -> Vec<u8>,
+    ) -> T {
+        assert!(i < self.len, "array index out of bounds");
+        self.id.check(memory);
+        let mem_offset = self.offset + i * T::LENGTH;
+        unsafe { T::read_bytes(&bytes(mem_offset, T::LENGTH, self.generation)) }
+    }
+
+    /// Panics if the array pointer can't be used with the memory.
+    ///
+    /// Every element is checked to be in bounds, so that computing the offset of an element
+    /// can't overflow, even for a malformed deserialized pointer.
+    #[inline]
+    fn check(&self, memory: &Memory) {
+        self.id.check(memory);
+        memory.check_allocation(self.generation);
+        assert!(
+            self.len
+                .checked_mul(T::LENGTH)
+                .and_then(|len| self.offset.checked_add(len))
+                .filter(|&end| end <= memory.len)
+                .is_some(),
+            "pointer out of bounds of the memory it was used with"
+        );
     }
 
     /// Sets the value of the given index of the array pointer in memory.
@@ -528,14 +1390,9 @@ where
     #[inline]
     pub fn set(&self, memory: &mut Memory, i: usize, val: T) {
         assert!(i < self.len, "array index out of bounds");
+        self.check(memory);
         let mem_offset = self.offset + i * T::LENGTH;
-        unsafe {
-            val.write_bytes(
-                memory
-                    .bytes
-                    .get_unchecked_mut(mem_offset..(mem_offset + T::LENGTH)),
-            );
-        }
+        unsafe { write_in_place(val, memory.bytes_mut(mem_offset, T::LENGTH)) }
     }
 
     /// Updates the value of the given index in memory using the given function.
@@ -592,16 +1449,191 @@ where
         self.set(memory, i, self.get(memory, j));
         self.set(memory, j, temp_i);
     }
-}
 
-impl<T> Clone for ArrayPointer<T> {
-    fn clone(&self) -> Self {
+    /// Returns a pointer to the values in the given range of the array.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let array = ArrayPointer::new(&mut builder, &[1, 2, 3, 4, 5]);
+    /// let memory = builder.finish();
+    ///
+    /// let tail = array.slice(2..);
+    /// assert_eq!(tail.len(), 3);
+    /// assert_eq!(tail.get(&memory, 0), 3);
+    /// ```
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let range = to_range(range, self.len);
         Self {
-            offset: self.offset,
-            len: self.len,
+            id: self.id,
+            offset: self.offset + range.start * T::LENGTH,
+            len: range.len(),
+            generation: self.generation,
             phantom: PhantomData,
         }
     }
+
+    /// Copies the values of the array pointer in memory to the given slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is different from the length of the array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let array = ArrayPointer::new(&mut builder, &['x', 'y']);
+    /// let memory = builder.finish();
+    ///
+    /// let mut out = ['\0'; 2];
+    /// array.read_into(&memory, &mut out);
+    /// assert_eq!(out, ['x', 'y']);
+    /// ```
+    pub fn read_into(&self, memory: &Memory, out: &mut [T]) {
+        assert_eq!(
+            out.len(),
+            self.len,
+            "the length of the slice doesn't match the length of the array"
+        );
+        self.check(memory);
+        let bytes = memory.bytes(self.offset, self.len * T::LENGTH);
+        for (val, val_bytes) in out.iter_mut().zip(bytes.chunks_exact(T::LENGTH.max(1))) {
+            *val = unsafe { read_in_place(val_bytes) };
+        }
+    }
+
+    /// Sets the values of the array pointer in memory to the values of the given slice.
+    ///
+    /// The values are written with a single write to the memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is different from the length of the array.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let array = ArrayPointer::new(&mut builder, &[0; 3]);
+    /// let mut memory = builder.finish();
+    ///
+    /// array.write_from(&mut memory, &[7, 8, 9]);
+    /// assert_eq!(array.get(&memory, 2), 9);
+    /// ```
+    pub fn write_from(&self, memory: &mut Memory, vals: &[T]) {
+        assert_eq!(
+            vals.len(),
+            self.len,
+            "the length of the slice doesn't match the length of the array"
+        );
+        let mut vals = vals.iter();
+        self.for_each_mut(memory, |val| *val = *vals.next().unwrap());
+    }
+
+    /// Sets every value of the array pointer in memory to the given value.
+    ///
+    /// The values are written with a single write to the memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let array = ArrayPointer::new(&mut builder, &[1, 2, 3]);
+    /// let mut memory = builder.finish();
+    ///
+    /// array.fill(&mut memory, 0);
+    /// assert_eq!(array.get(&memory, 1), 0);
+    /// ```
+    pub fn fill(&self, memory: &mut Memory, val: T) {
+        self.for_each_mut(memory, |x| *x = val);
+    }
+
+    /// Copies the values in the range `src` of the array pointer in memory to the indices
+    /// starting at `dest`. The ranges may overlap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either range is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let array = ArrayPointer::new(&mut builder, &[1, 2, 3, 4, 5]);
+    /// let mut memory = builder.finish();
+    ///
+    /// array.copy_within(&mut memory, 0..3, 2);
+    /// assert_eq!(array.as_slice(&memory), &[1, 2, 1, 2, 3]);
+    /// ```
+    pub fn copy_within(&self, memory: &mut Memory, src: impl RangeBounds<usize>, dest: usize) {
+        let src = to_range(src, self.len);
+        assert!(
+            dest <= self.len - src.len(),
+            "destination range out of bounds"
+        );
+        self.check(memory);
+        let start = self.offset + src.start * T::LENGTH;
+        memory.copy_within(
+            start..(start + src.len() * T::LENGTH),
+            self.offset + dest * T::LENGTH,
+        );
+    }
+
+    /// Updates every value of the array pointer in memory in place using the given function.
+    ///
+    /// The values are written with a single write to the memory, even if the function leaves
+    /// some of them unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use contrail::mem::{ArrayPointer, MemoryBuilder};
+    ///
+    /// let mut builder = MemoryBuilder::new();
+    /// let array = ArrayPointer::new(&mut builder, &[1, 2, 3]);
+    /// let mut memory = builder.finish();
+    ///
+    /// array.for_each_mut(&mut memory, |x| *x *= 10);
+    /// assert_eq!(array.as_slice(&memory), &[10, 20, 30]);
+    /// ```
+    pub fn for_each_mut(&self, memory: &mut Memory, mut f: impl FnMut(&mut T)) {
+        self.check(memory);
+        let bytes = memory.bytes_mut(self.offset, self.len * T::LENGTH);
+        if T::LENGTH == 0 {
+            // every value of a zero-sized type is the same
+            for _ in 0..self.len {
+                f(&mut unsafe { read_in_place::<T>(bytes) });
+            }
+            return;
+        }
+        for val_bytes in bytes.chunks_exact_mut(T::LENGTH) {
+            let mut val = unsafe { read_in_place::<T>(val_bytes) };
+            f(&mut val);
+            unsafe { write_in_place(val, val_bytes) };
+        }
+    }
+}
+
+impl<T> Clone for ArrayPointer<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
 impl<T> Copy for ArrayPointer<T> {}
@@ -619,7 +1651,10 @@ impl<T> Eq for ArrayPointer<T> {}
 
 impl<T> PartialEq for ArrayPointer<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.offset == other.offset && self.len == other.len
+        self.id == other.id
+            && self.offset == other.offset
+            && self.len == other.len
+            && self.generation == other.generation
     }
 }
 
@@ -631,20 +1666,31 @@ macro_rules! impl_bytes_primitive {
 
                 #[inline]
                 unsafe fn read_bytes(bytes: &[u8]) -> $T {
-                    // safe assuming that the length of the byte slice is Self::LENGTH.
-                    let byte_array = *(bytes.as_ptr() as *const [u8; std::mem::size_of::<$T>()]);
-                    // safe assuming that the byte slice represents a valid value of type T.
-                    std::mem::transmute::<[u8; std::mem::size_of::<$T>()], $T>(byte_array)
+                    // safe assuming that the length of the byte slice is Self::LENGTH and that
+                    // the byte slice represents a valid value of type T.
+                    std::ptr::read_unaligned(bytes.as_ptr() as *const $T)
                 }
 
                 #[inline]
                 unsafe fn write_bytes(self, bytes: &mut [u8]) {
-                    // safe for Copy + 'static types
-                    let byte_array = std::mem::transmute::<$T, [u8; std::mem::size_of::<$T>()]>(self);
                     // safe assuming that the length of the byte slice is Self::LENGTH.
-                    bytes.copy_from_slice(&byte_array);
+                    std::ptr::write_unaligned(bytes.as_mut_ptr() as *mut $T, self);
+                }
+
+                #[inline]
+                unsafe fn read_aligned(bytes: &[u8]) -> $T {
+                    // safe assuming the same as `read_bytes` and that the byte slice is aligned.
+                    std::ptr::read(bytes.as_ptr() as *const $T)
+                }
+
+                #[inline]
+                unsafe fn write_aligned(self, bytes: &mut [u8]) {
+                    // safe assuming the same as `write_bytes` and that the byte slice is aligned.
+                    std::ptr::write(bytes.as_mut_ptr() as *mut $T, self);
                 }
             }
+
+            unsafe impl Plain for $T {}
         )*
     }
 }
@@ -658,7 +1704,208 @@ impl_bytes_primitive! {
     (),
 }
 
+/// The serialized form of `Pointer`.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[serde(rename = "Pointer")]
+struct SerializePointer {
+    id: MemoryId,
+    offset: usize,
+    generation: Option<Generation>,
+    type_name: &'static str,
+    size: usize,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Pointer")]
+struct DeserializePointer {
+    id: MemoryId,
+    offset: usize,
+    #[serde(default)]
+    generation: Option<Generation>,
+    type_name: String,
+    size: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<T> Serialize for Pointer<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializePointer {
+            id: self.id,
+            offset: self.offset,
+            generation: self.generation,
+            type_name: std::any::type_name::<T>(),
+            size: mem::size_of::<T>(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> Deserialize<'de> for Pointer<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pointer = DeserializePointer::deserialize(deserializer)?;
+        check_type::<T, D::Error>(&pointer.type_name, pointer.size)?;
+        Ok(Pointer {
+            id: pointer.id,
+            offset: pointer.offset,
+            generation: pointer.generation,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// The serialized form of `ArrayPointer`.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[serde(rename = "ArrayPointer")]
+struct SerializeArrayPointer {
+    id: MemoryId,
+    offset: usize,
+    len: usize,
+    generation: Option<Generation>,
+    type_name: &'static str,
+    size: usize,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "ArrayPointer")]
+struct DeserializeArrayPointer {
+    id: MemoryId,
+    offset: usize,
+    len: usize,
+    #[serde(default)]
+    generation: Option<Generation>,
+    type_name: String,
+    size: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<T> Serialize for ArrayPointer<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeArrayPointer {
+            id: self.id,
+            offset: self.offset,
+            len: self.len,
+            generation: self.generation,
+            type_name: std::any::type_name::<T>(),
+            size: mem::size_of::<T>(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> Deserialize<'de> for ArrayPointer<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pointer = DeserializeArrayPointer::deserialize(deserializer)?;
+        check_type::<T, D::Error>(&pointer.type_name, pointer.size)?;
+        Ok(ArrayPointer {
+            id: pointer.id,
+            offset: pointer.offset,
+            len: pointer.len,
+            generation: pointer.generation,
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// Fails unless the name and size recorded by a serialized pointer are the name and size of `T`,
+/// so that a pointer can't be deserialized as a pointer to a different type.
+#[cfg(feature = "serde")]
+fn check_type<T, E: de::Error>(type_name: &str, size: usize) -> Result<(), E> {
+    if type_name != std::any::type_name::<T>() || size != mem::size_of::<T>() {
+        return Err(E::custom(format_args!(
+            "pointer to `{}` deserialized as a pointer to `{}`",
+            type_name,
+            std::any::type_name::<T>()
+        )));
+    }
+    Ok(())
+}
+
+/// The serialized form of `Memory`.
+///
+/// Only the bytes in use are serialized, the undo log is serialized as the offset and length of
+/// each entry followed by the overwritten bytes, and the allocations are serialized with their
+/// generations so that allocated pointers stay usable.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[serde(rename = "Memory")]
+struct SerializeMemory<'a> {
+    id: MemoryId,
+    layout_len: usize,
+    bytes: &'a [u8],
+    written: usize,
+    entries: Vec<(usize, usize)>,
+    saved: &'a [u8],
+    allocations: &'a [(usize, Generation)],
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Memory")]
+struct DeserializeMemory {
+    id: MemoryId,
+    layout_len: usize,
+    bytes: Vec<u8>,
+    written: usize,
+    entries: Vec<(usize, usize)>,
+    saved: Vec<u8>,
+    allocations: Vec<(usize, Generation)>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Memory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (entries, saved) = self.journal.parts();
+        SerializeMemory {
+            id: self.id,
+            layout_len: self.layout_len,
+            bytes: self.as_slice(),
+            written: self.written,
+            entries: entries.collect(),
+            saved,
+            allocations: &self.allocations,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Memory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let memory = DeserializeMemory::deserialize(deserializer)?;
+        let len = memory.bytes.len();
+        if memory.layout_len > len {
+            return Err(de::Error::custom("layout longer than the memory"));
+        }
+        let journal = Journal::from_parts(memory.entries, memory.saved)
+            .ok_or_else(|| de::Error::custom("undo log entries don't match overwritten bytes"))?;
+        if !is_allocation_list(&memory.allocations)
+            || memory.allocations.iter().any(|&(end, _)| end > len)
+        {
+            return Err(de::Error::custom(
+                "allocations out of order or out of bounds",
+            ));
+        }
+        Ok(Memory {
+            id: memory.id,
+            bytes: AlignedBytes::from(&memory.bytes[..]),
+            len,
+            layout_len: memory.layout_len,
+            allocations: memory.allocations,
+            journal,
+            written: memory.written,
+            writes: 0,
+        })
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::len_zero, clippy::needless_range_loop)]
 mod tests {
     use super::*;
 
@@ -713,6 +1960,28 @@ mod tests {
         [(), unit],
     );
 
+    mod memory {
+        use super::*;
+
+        #[test]
+        fn eq() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = Pointer::new(&mut builder, 7u8);
+            let memory = builder.finish();
+
+            let mut other = memory.clone();
+            pointer.set(&mut other, 7);
+            Pointer::alloc(&mut other, 8u8);
+            assert_ne!(other, memory);
+            other.set_len(1);
+            assert_eq!(other, memory);
+
+            let mut builder = MemoryBuilder::new();
+            Pointer::new(&mut builder, 7u8);
+            assert_eq!(builder.finish(), memory);
+        }
+    }
+
     mod pointer {
         use super::*;
 
@@ -734,6 +2003,21 @@ mod tests {
             assert_eq!(pointer, pointer.clone());
         }
 
+        #[test]
+        #[cfg(any(debug_assertions, feature = "checked"))]
+        #[should_panic(
+            expected = "pointer used with memory other than the memory it was created for"
+        )]
+        fn other_memory() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = Pointer::new(&mut builder, 5);
+            let mut other = MemoryBuilder::new();
+            Pointer::new(&mut other, 5);
+            let other = other.finish();
+
+            pointer.get(&other);
+        }
+
         #[test]
         fn get_set_update() {
             let mut builder = MemoryBuilder::new();
@@ -748,6 +2032,32 @@ mod tests {
             pointer.update(&mut memory, |x| x - 1);
             assert_eq!(pointer.get(&memory), 5);
         }
+
+        #[test]
+        #[cfg(feature = "serde")]
+        #[should_panic(expected = "pointer out of bounds of the memory it was used with")]
+        fn malformed() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = Pointer::new(&mut builder, 5_u32);
+            let memory = builder.finish();
+
+            let mut json = serde_json::to_value(pointer).unwrap();
+            json["offset"] = serde_json::json!(1 << 20);
+            let malformed: Pointer<u32> = serde_json::from_value(json).unwrap();
+
+            malformed.get(&memory);
+        }
+
+        #[test]
+        #[cfg(feature = "serde")]
+        fn wrong_type() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = Pointer::new(&mut builder, 5_u8);
+
+            let json = serde_json::to_string(&pointer).unwrap();
+            assert!(serde_json::from_str::<Pointer<bool>>(&json).is_err());
+            assert!(serde_json::from_str::<Pointer<u8>>(&json).is_ok());
+        }
     }
 
     mod array_pointer {
@@ -777,6 +2087,48 @@ mod tests {
             assert_eq!(pointer, pointer.clone());
         }
 
+        #[test]
+        #[cfg(feature = "serde")]
+        #[should_panic(expected = "pointer out of bounds of the memory it was used with")]
+        fn malformed() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[1_u8, 2, 3]);
+            let memory = builder.finish();
+
+            let mut json = serde_json::to_value(pointer).unwrap();
+            json["offset"] = serde_json::json!(usize::MAX);
+            let malformed: ArrayPointer<u8> = serde_json::from_value(json).unwrap();
+
+            malformed.as_slice(&memory);
+        }
+
+        #[test]
+        #[cfg(feature = "serde")]
+        fn wrong_type() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[1_u32, 2]);
+
+            let json = serde_json::to_string(&pointer).unwrap();
+            assert!(serde_json::from_str::<ArrayPointer<char>>(&json).is_err());
+            assert!(serde_json::from_str::<ArrayPointer<u32>>(&json).is_ok());
+        }
+
+        #[test]
+        #[cfg(feature = "serde")]
+        #[should_panic(expected = "pointer out of bounds of the memory it was used with")]
+        fn malformed_len() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[1_u64, 2, 3]);
+            let memory = builder.finish();
+
+            // the length of the array in bytes overflows to 8
+            let mut json = serde_json::to_value(pointer).unwrap();
+            json["len"] = serde_json::json!(usize::MAX / 8 + 2);
+            let malformed: ArrayPointer<u64> = serde_json::from_value(json).unwrap();
+
+            malformed.as_slice(&memory);
+        }
+
         #[test]
         fn empty() {
             let mut builder = MemoryBuilder::new();
@@ -822,5 +2174,193 @@ mod tests {
             assert_eq!(pointer.get(&memory, 0), 'z');
             assert_eq!(pointer.get(&memory, 1), 'a');
         }
+
+        #[test]
+        fn slice() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[0u16, 1, 2, 3, 4]);
+            let memory = builder.finish();
+
+            assert_eq!(pointer.slice(..).range(), pointer.range());
+            assert_eq!(pointer.slice(1..=2).get(&memory, 1), 2);
+            assert_eq!(pointer.slice(4..).len(), 1);
+            assert!(pointer.slice(5..).is_empty());
+            assert!(pointer.slice(2..2).is_empty());
+        }
+
+        #[test]
+        #[should_panic(expected = "range out of bounds")]
+        fn slice_out_of_bounds() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[0u8; 3]);
+            pointer.slice(1..4);
+        }
+
+        #[test]
+        fn bulk() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[0u32; 5]);
+            let mut memory = builder.finish();
+
+            pointer.write_from(&mut memory, &[1, 2, 3, 4, 5]);
+            pointer.copy_within(&mut memory, 3.., 0);
+            pointer.slice(2..4).fill(&mut memory, 9);
+            pointer.for_each_mut(&mut memory, |x| *x += 1);
+
+            let mut out = [0; 5];
+            pointer.read_into(&memory, &mut out);
+            assert_eq!(out, [5, 6, 10, 10, 6]);
+        }
+
+        #[test]
+        fn bulk_zero_sized() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[(); 3]);
+            let mut memory = builder.finish();
+
+            let mut calls = 0;
+            pointer.for_each_mut(&mut memory, |_| calls += 1);
+            pointer.write_from(&mut memory, &[(); 3]);
+            pointer.copy_within(&mut memory, 1.., 0);
+            assert_eq!(calls, 3);
+        }
+
+        #[test]
+        #[should_panic(expected = "the length of the slice doesn't match the length of the array")]
+        fn write_from_wrong_len() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[0u8; 3]);
+            let mut memory = builder.finish();
+
+            pointer.write_from(&mut memory, &[1, 2]);
+        }
+
+        #[test]
+        #[should_panic(expected = "destination range out of bounds")]
+        fn copy_within_out_of_bounds() {
+            let mut builder = MemoryBuilder::new();
+            let pointer = ArrayPointer::new(&mut builder, &[0u8; 3]);
+            let mut memory = builder.finish();
+
+            pointer.copy_within(&mut memory, ..2, 2);
+        }
+
+        #[test]
+        fn as_slice() {
+            let mut builder = MemoryBuilder::new();
+            let empty = ArrayPointer::<u64>::new(&mut builder, &[]);
+            let bytes = ArrayPointer::new(&mut builder, &[1u8, 2, 3]);
+            let wide = ArrayPointer::new(&mut builder, &[4u128, 5]);
+            let mut memory = builder.finish();
+
+            wide.set(&mut memory, 1, 6);
+            assert!(empty.as_slice(&memory).is_empty());
+            assert_eq!(bytes.as_slice(&memory), &[1, 2, 3]);
+            assert_eq!(wide.as_slice(&memory), &[4, 6]);
+        }
+    }
+
+    mod alignment {
+        use super::*;
+
+        fn is_aligned<T>(memory: &Memory, offset: usize) -> bool {
+            (memory.as_slice().as_ptr() as usize + offset) & (align_of::<T>() - 1) == 0
+        }
+
+        #[test]
+        fn builder() {
+            let mut builder = MemoryBuilder::new();
+            let byte = Pointer::new(&mut builder, 1u8);
+            let word = Pointer::new(&mut builder, 2u64);
+            let halves = ArrayPointer::new(&mut builder, &[3u16, 4, 5]);
+            let wide = Pointer::new(&mut builder, 6u128);
+            let memory = builder.finish();
+
+            assert_eq!(byte.range(), 0..1);
+            assert_eq!(word.range(), 8..16);
+            assert_eq!(halves.range(), 16..22);
+            assert_eq!(wide.range(), 32..48);
+            assert!(is_aligned::<u64>(&memory, word.range().start));
+            assert!(is_aligned::<u128>(&memory, wide.range().start));
+            assert_eq!(wide.get(&memory), 6);
+        }
+
+        #[test]
+        fn alloc() {
+            let mut builder = MemoryBuilder::new();
+            Pointer::new(&mut builder, 1u8);
+            let mut memory = builder.finish();
+
+            let word = Pointer::alloc(&mut memory, 2u32);
+            let words = ArrayPointer::alloc(&mut memory, &[3u64, 4]);
+            assert_eq!(word.range(), 4..8);
+            assert_eq!(words.range(), 8..24);
+            assert_eq!(words.as_slice(&memory), &[3, 4]);
+
+            // the buffer stays aligned when it grows
+            memory.set_len(1);
+            let many = ArrayPointer::alloc(&mut memory, &[7u128; 100]);
+            assert!(is_aligned::<u128>(&memory, many.range().start));
+            assert_eq!(many.get(&memory, 99), 7);
+        }
+
+        #[test]
+        fn over_aligned() {
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            #[repr(align(32))]
+            struct Aligned(u8);
+
+            impl Bytes for Aligned {
+                const LENGTH: usize = 1;
+
+                unsafe fn read_bytes(bytes: &[u8]) -> Self {
+                    Aligned(bytes[0])
+                }
+
+                unsafe fn write_bytes(self, bytes: &mut [u8]) {
+                    bytes[0] = self.0;
+                }
+            }
+
+            let mut builder = MemoryBuilder::new();
+            Pointer::new(&mut builder, 1u8);
+            let pointer = Pointer::new(&mut builder, Aligned(2));
+            let mut memory = builder.finish();
+
+            assert_eq!(pointer.range(), MAX_ALIGN..(MAX_ALIGN + 1));
+            pointer.set(&mut memory, Aligned(3));
+            assert_eq!(pointer.get(&memory), Aligned(3));
+        }
+
+        #[test]
+        fn unaligned_length() {
+            #[derive(Clone, Copy, Debug, PartialEq)]
+            struct Packed(u16, u8);
+
+            impl Bytes for Packed {
+                const LENGTH: usize = 3;
+
+                unsafe fn read_bytes(bytes: &[u8]) -> Self {
+                    Packed(u16::from_le_bytes([bytes[0], bytes[1]]), bytes[2])
+                }
+
+                unsafe fn write_bytes(self, bytes: &mut [u8]) {
+                    bytes[..2].copy_from_slice(&self.0.to_le_bytes());
+                    bytes[2] = self.1;
+                }
+            }
+
+            let mut builder = MemoryBuilder::new();
+            let array = ArrayPointer::new(&mut builder, &[Packed(1, 2), Packed(3, 4)]);
+            let mut memory = builder.finish();
+
+            // the second element is at an odd offset
+            assert_eq!(array.range(), 0..6);
+            assert_eq!(array.get(&memory, 1), Packed(3, 4));
+            array.set(&mut memory, 1, Packed(5, 6));
+            array.update(&mut memory, 0, |Packed(a, b)| Packed(a + 1, b));
+            assert_eq!(array.get(&memory, 0), Packed(2, 2));
+            assert_eq!(array.get(&memory, 1), Packed(5, 6));
+        }
     }
 }
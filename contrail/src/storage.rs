@@ -5,8 +5,154 @@
  */
 
 //! High-level memory management.
-use crate::{Memory, MemoryBuilder, Trail, TrailBuilder};
-use std::fmt;
+//!
+//! # User-defined storage modes
+//!
+//! Besides [`Backtrackable`](Backtrackable) and [`NonBacktrackable`](NonBacktrackable), a trail
+//! can hold any number of memories for storage modes defined outside of this crate. A storage
+//! mode is a marker type that implements [`StorageMode`](StorageMode), choosing a name and a
+//! [`RestorePolicy`](RestorePolicy) that decides what happens to its memory as levels are added
+//! to and removed from the trail. The memory is created the first time a value is created with
+//! the storage mode, and `Value`, `Array` and every collection generic over `M: StorageMode` work
+//! with it unchanged.
+//!
+//! # Examples
+//!
+//! A storage mode that's restored like backtrackable memory but kept in a memory of its own:
+//!
+//! ```
+//! use contrail::{
+//!     storage::{StorageMode, Undo},
+//!     Array, TrailBuilder,
+//! };
+//!
+//! #[derive(Clone, Copy, Debug, Default)]
+//! struct Scratch;
+//!
+//! impl StorageMode for Scratch {
+//!     const NAME: &'static str = "Scratch";
+//!     type Policy = Undo;
+//! }
+//!
+//! let mut builder = TrailBuilder::new();
+//! let scratch = Array::<Scratch, u32>::new(&mut builder, vec![0; 4]);
+//! let mut trail = builder.finish();
+//!
+//! trail.new_level();
+//! scratch.set(&mut trail, 2, 7);
+//! trail.backtrack();
+//! assert_eq!(scratch.get(&trail, 2), 0);
+//! ```
+//!
+//! A policy the crate doesn't provide, which keeps changes made at every level except the root:
+//!
+//! ```
+//! use contrail::{
+//!     mem::Memory,
+//!     storage::{RestorePolicy, StorageMode},
+//!     TrailBuilder, Value,
+//! };
+//!
+//! #[derive(Debug, Default)]
+//! struct RestoreRoot {
+//!     root: Option<Vec<u8>>,
+//! }
+//!
+//! impl RestorePolicy for RestoreRoot {
+//!     fn new_level(&mut self, memory: &mut Memory, depth: usize) {
+//!         if depth == 1 {
+//!             self.root = Some(memory.as_slice().to_vec());
+//!         }
+//!     }
+//!
+//!     fn backtrack(&mut self, memory: &mut Memory, depth: usize) {
+//!         if depth == 0 {
+//!             if let Some(root) = self.root.take() {
+//!                 // SAFETY: `root` is a copy of the same memory taken at the root level
+//!                 unsafe { memory.copy_from_slice(&root) };
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(Clone, Copy, Debug, Default)]
+//! struct Episode;
+//!
+//! impl StorageMode for Episode {
+//!     const NAME: &'static str = "Episode";
+//!     type Policy = RestoreRoot;
+//! }
+//!
+//! let mut builder = TrailBuilder::new();
+//! let visits = Value::<Episode, u32>::new(&mut builder, 0);
+//! let mut trail = builder.finish();
+//!
+//! trail.new_level();
+//! for _ in 0..3 {
+//!     trail.new_level();
+//!     visits.update(&mut trail, |x| x + 1);
+//!     trail.backtrack();
+//! }
+//! assert_eq!(visits.get(&trail), 3);
+//!
+//! trail.backtrack();
+//! assert_eq!(visits.get(&trail), 0);
+//! ```
+
+use crate::{journal::Recording, Memory, MemoryBuilder, Trail, TrailBuilder};
+use std::{any::TypeId, fmt};
+
+/// Representation of where something is stored on the trail that can be read from.
+///
+/// Every [`StorageMode`](StorageMode) implements `Readable`, as does [`Constant`](Constant), whose
+/// values can only be read once the trail is finished. Methods of `Value` and `Array` that only
+/// create or read values are available for every `Readable` type, while methods that write
+/// require a `StorageMode`.
+///
+/// Storage modes defined outside of this crate should implement `StorageMode`, which implements
+/// `Readable` for them.
+pub trait Readable: fmt::Debug + Default + 'static {
+    /// The name of the storage, as shown by [`Trail::inspect`](Trail::inspect).
+    const NAME: &'static str;
+
+    /// Returns the associated `MemoryBuilder` from a `TrailBuilder`.
+    fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder;
+
+    /// Returns the region of the associated memory in a `TrailBuilder`, which is passed to
+    /// [`memory`](Readable::memory) to find the memory on the finished trail without searching
+    /// for it. Storage with a memory of its own on every trail returns 0.
+    #[inline]
+    fn region(builder: &mut TrailBuilder) -> usize {
+        let _ = builder;
+        0
+    }
+
+    /// Returns the associated `Memory` from a `Trail`, given the region returned by
+    /// [`region`](Readable::region).
+    fn memory(trail: &Trail, region: usize) -> &Memory;
+}
+
+impl<M> Readable for M
+where
+    M: StorageMode,
+{
+    const NAME: &'static str = <M as StorageMode>::NAME;
+
+    #[inline]
+    fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder {
+        <M as StorageMode>::builder_mut(builder)
+    }
+
+    #[inline]
+    fn region(builder: &mut TrailBuilder) -> usize {
+        <M as StorageMode>::region(builder)
+    }
+
+    #[inline]
+    fn memory(trail: &Trail, region: usize) -> &Memory {
+        <M as StorageMode>::memory(trail, region)
+    }
+}
 
 /// Representation of how something is stored on the trail.
 ///
@@ -14,17 +160,129 @@ use std::fmt;
 /// [`Backtrackable`](Backtrackable) and [`NonBacktrackable`](NonBacktrackable), respectively. Both
 /// of these structs implement `StorageMode`. See the documentation for [`Trail`](Trail) for the
 /// difference between backtrackable and non-backtrackable storage.
-pub trait StorageMode: fmt::Debug + Default {
+///
+/// Other storage modes can be defined by implementing this trait for a marker type, which only
+/// requires a name and a restore policy: each storage mode gets a memory of its own on the trail.
+/// See the [module documentation](self) for examples.
+pub trait StorageMode: fmt::Debug + Default + 'static {
+    /// The name of the storage mode, as shown by [`Trail::inspect`](Trail::inspect).
+    ///
+    /// The name should be different from the name of every other storage mode used with the
+    /// same trail.
+    const NAME: &'static str;
+
+    /// How the memory of the storage mode is restored as levels are added to and removed from
+    /// the trail.
+    ///
+    /// The backtrackable and non-backtrackable memories are restored by the trail itself, so the
+    /// policies of `Backtrackable` and `NonBacktrackable` only describe their behavior.
+    type Policy: RestorePolicy + Default;
+
     /// Returns the associated `MemoryBuilder` from a `TrailBuilder`.
-    fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder;
+    fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder {
+        builder.regions.builder_mut::<Self>()
+    }
+
+    /// Returns the region of the associated memory in a `TrailBuilder`, which is passed to
+    /// [`memory`](StorageMode::memory) and [`memory_mut`](StorageMode::memory_mut) to find the
+    /// memory on the finished trail without searching for it.
+    fn region(builder: &mut TrailBuilder) -> usize {
+        builder.regions.index::<Self>()
+    }
+
+    /// Returns the associated `Memory` from a `Trail`, given the region returned by
+    /// [`region`](StorageMode::region). The memory is searched for if the region is wrong.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no value was created with the storage mode before the trail was finished.
+    fn memory(trail: &Trail, region: usize) -> &Memory {
+        trail.regions.memory::<Self>(region)
+    }
+
+    /// Returns the associated `Memory` from a `Trail` mutably, given the region returned by
+    /// [`region`](StorageMode::region). The memory is searched for if the region is wrong.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no value was created with the storage mode before the trail was finished.
+    fn memory_mut(trail: &mut Trail, region: usize) -> &mut Memory {
+        trail.regions.memory_mut::<Self>(region)
+    }
+}
+
+/// A policy that restores the memory of a [`StorageMode`](StorageMode) as levels are added to
+/// and removed from the trail.
+///
+/// Every method is called after the trail has changed, with the memory of the storage mode and
+/// the new length of the trail, and does nothing by default.
+pub trait RestorePolicy: Send + Sync + 'static {
+    /// Called after a level is added to the trail.
+    fn new_level(&mut self, memory: &mut Memory, depth: usize) {
+        let _ = (memory, depth);
+    }
 
-    /// Returns the associated `Memory` from a `Trail`.
-    fn memory(trail: &Trail) -> &Memory;
+    /// Called after one or more levels are removed from the trail by backtracking.
+    fn backtrack(&mut self, memory: &mut Memory, depth: usize) {
+        let _ = (memory, depth);
+    }
 
-    /// Returns the associated `Memory` from a `Trail` mutably.
-    fn memory_mut(trail: &mut Trail) -> &mut Memory;
+    /// Called after the most recent level is removed from the trail while keeping its changes.
+    fn commit_level(&mut self, memory: &mut Memory, depth: usize) {
+        let _ = (memory, depth);
+    }
+}
+
+/// A restore policy that records every write in an undo log, so that the memory is restored to
+/// its state when a level was added whenever the level is removed.
+///
+/// This is how backtrackable memory behaves with [`Strategy::Log`](crate::Strategy::Log).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Undo {
+    /// The depth of each level added since the policy was created, along with the length of the
+    /// undo log when it was added.
+    marks: Vec<(usize, usize)>,
+}
+
+impl RestorePolicy for Undo {
+    fn new_level(&mut self, memory: &mut Memory, depth: usize) {
+        self.marks.push((depth, memory.journal().mark()));
+        memory.journal_mut().set_recording(Recording::Writes);
+    }
+
+    fn backtrack(&mut self, memory: &mut Memory, depth: usize) {
+        let kept = self.marks.iter().take_while(|&&(d, _)| d <= depth).count();
+        if let Some(&(_, mark)) = self.marks.get(kept) {
+            memory.undo(mark);
+            self.marks.truncate(kept);
+        }
+        if self.marks.is_empty() {
+            memory.journal_mut().set_recording(Recording::Off);
+        }
+    }
+
+    fn commit_level(&mut self, memory: &mut Memory, depth: usize) {
+        if let Some(&(d, mark)) = self.marks.last() {
+            if d > depth {
+                self.marks.pop();
+                if self.marks.is_empty() {
+                    // there's nothing left to restore
+                    let journal = memory.journal_mut();
+                    journal.truncate(mark);
+                    journal.set_recording(Recording::Off);
+                }
+            }
+        }
+    }
 }
 
+/// A restore policy that never restores the memory, so that changes are kept when levels are
+/// removed.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Keep;
+
+impl RestorePolicy for Keep {}
+
 /// Objects stored on the trail in backtrackable memory.
 ///
 /// Instead of using `Backtrackable` directly, it's often easier to use the type definitions
@@ -56,18 +314,26 @@ pub trait StorageMode: fmt::Debug + Default {
 pub struct Backtrackable;
 
 impl StorageMode for Backtrackable {
+    const NAME: &'static str = "Backtrackable";
+    type Policy = Undo;
+
     #[inline]
     fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder {
         &mut builder.backtrackable_mem
     }
 
     #[inline]
-    fn memory(trail: &Trail) -> &Memory {
+    fn region(_builder: &mut TrailBuilder) -> usize {
+        0
+    }
+
+    #[inline]
+    fn memory(trail: &Trail, _region: usize) -> &Memory {
         &trail.backtrackable_mem
     }
 
     #[inline]
-    fn memory_mut(trail: &mut Trail) -> &mut Memory {
+    fn memory_mut(trail: &mut Trail, _region: usize) -> &mut Memory {
         &mut trail.backtrackable_mem
     }
 }
@@ -103,18 +369,514 @@ impl StorageMode for Backtrackable {
 pub struct NonBacktrackable;
 
 impl StorageMode for NonBacktrackable {
+    const NAME: &'static str = "NonBacktrackable";
+    type Policy = Keep;
+
     #[inline]
     fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder {
         &mut builder.non_backtrackable_mem
     }
 
     #[inline]
-    fn memory(trail: &Trail) -> &Memory {
+    fn region(_builder: &mut TrailBuilder) -> usize {
+        0
+    }
+
+    #[inline]
+    fn memory(trail: &Trail, _region: usize) -> &Memory {
         &trail.non_backtrackable_mem
     }
 
     #[inline]
-    fn memory_mut(trail: &mut Trail) -> &mut Memory {
+    fn memory_mut(trail: &mut Trail, _region: usize) -> &mut Memory {
         &mut trail.non_backtrackable_mem
     }
 }
+
+/// Objects stored on the trail in restart memory.
+///
+/// Restart memory behaves like non-backtrackable memory, keeping every change when levels are
+/// removed, except that [`Trail::restart`](Trail::restart) resets it to its contents when the
+/// `TrailBuilder` was finished. This suits state that lives for a single run of a search, such as
+/// per-run counters, phase-saving tables and restart-local heuristics.
+///
+/// Instead of using `Restart` directly, it's often easier to use the type definitions
+/// [`RestartValue`](crate::RestartValue) and [`RestartArray`](crate::RestartArray).
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{RestartValue, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let conflicts = RestartValue::new(&mut builder, 0);
+/// let mut trail = builder.finish();
+///
+/// trail.new_level();
+/// conflicts.update(&mut trail, |x| x + 1);
+/// trail.backtrack();
+/// assert_eq!(conflicts.get(&trail), 1);
+///
+/// trail.restart();
+/// assert_eq!(conflicts.get(&trail), 0);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Restart;
+
+impl StorageMode for Restart {
+    const NAME: &'static str = "Restart";
+    type Policy = Keep;
+
+    #[inline]
+    fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder {
+        &mut builder.restart_mem
+    }
+
+    #[inline]
+    fn region(_builder: &mut TrailBuilder) -> usize {
+        0
+    }
+
+    #[inline]
+    fn memory(trail: &Trail, _region: usize) -> &Memory {
+        &trail.restart_mem
+    }
+
+    #[inline]
+    fn memory_mut(trail: &mut Trail, _region: usize) -> &mut Memory {
+        &mut trail.restart_mem
+    }
+}
+
+/// Objects stored on the trail in constant memory.
+///
+/// A value or array stored in constant memory gets its contents when it's created with a
+/// `TrailBuilder`, and can only be read once the trail is finished: `Value<Constant, T>` and
+/// `Array<Constant, T>` have no methods that write. Constant memory is kept apart from the other
+/// memories, so it's never copied when a level is added, and it's shared by every trail created
+/// with [`Trail::fork`](Trail::fork).
+///
+/// `Constant` only implements [`Readable`](Readable), not [`StorageMode`](StorageMode), so it
+/// can't be used with collections or anything else that writes to its values.
+///
+/// Instead of using `Constant` directly, it's often easier to use the type definitions
+/// [`ConstantValue`](crate::ConstantValue) and [`ConstantArray`](crate::ConstantArray).
+///
+/// # Examples
+///
+/// ```
+/// use contrail::{BacktrackableValue, ConstantArray, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let weights = ConstantArray::new(&mut builder, vec![3, 1, 4]);
+/// let total = BacktrackableValue::new(&mut builder, 0);
+/// let mut trail = builder.finish();
+///
+/// trail.new_level();
+/// let weight = weights.get(&trail, 2);
+/// total.update(&mut trail, |x| x + weight);
+/// assert_eq!(total.get(&trail), 4);
+///
+/// // only `total` is saved by the level
+/// trail.backtrack();
+/// assert_eq!(total.get(&trail), 0);
+/// assert_eq!(weights.iter(&trail).sum::<i32>(), 8);
+/// ```
+///
+/// Writing to a constant value doesn't compile:
+///
+/// ```compile_fail
+/// use contrail::{ConstantValue, TrailBuilder};
+///
+/// let mut builder = TrailBuilder::new();
+/// let value = ConstantValue::new(&mut builder, 1);
+/// let mut trail = builder.finish();
+///
+/// // error: `set` requires a `StorageMode`
+/// value.set(&mut trail, 2);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Constant;
+
+impl Readable for Constant {
+    const NAME: &'static str = "Constant";
+
+    #[inline]
+    fn builder_mut(builder: &mut TrailBuilder) -> &mut MemoryBuilder {
+        &mut builder.constant_mem
+    }
+
+    #[inline]
+    fn memory(trail: &Trail, _region: usize) -> &Memory {
+        &trail.constant_mem
+    }
+}
+
+/// The memory of a user-defined storage mode while it's being built.
+#[derive(Debug)]
+struct RegionBuilder {
+    type_id: TypeId,
+    name: &'static str,
+    memory: MemoryBuilder,
+    new_policy: fn() -> Box<dyn RestorePolicy>,
+}
+
+/// The memories of user-defined storage modes of a `TrailBuilder`.
+#[derive(Debug, Default)]
+pub(crate) struct RegionBuilders {
+    regions: Vec<RegionBuilder>,
+}
+
+impl RegionBuilders {
+    /// Returns the memory builder of the storage mode `M`, adding one if there isn't one yet.
+    pub(crate) fn builder_mut<M: StorageMode>(&mut self) -> &mut MemoryBuilder {
+        let index = self.index::<M>();
+        &mut self.regions[index].memory
+    }
+
+    /// Returns the index of the memory builder of the storage mode `M`, adding one if there
+    /// isn't one yet. The memory has the same index on the finished trail.
+    pub(crate) fn index<M: StorageMode>(&mut self) -> usize {
+        let type_id = TypeId::of::<M>();
+        match self.regions.iter().position(|r| r.type_id == type_id) {
+            Some(index) => index,
+            None => {
+                self.regions.push(RegionBuilder {
+                    type_id,
+                    name: M::NAME,
+                    memory: MemoryBuilder::new(),
+                    new_policy: || Box::new(M::Policy::default()),
+                });
+                self.regions.len() - 1
+            }
+        }
+    }
+
+    /// Creates the memories and their policies.
+    pub(crate) fn finish(self) -> Regions {
+        let mut regions = Regions::default();
+        regions.append(self);
+        regions
+    }
+}
+
+/// The memory of a user-defined storage mode and the policy that restores it.
+struct Region {
+    type_id: TypeId,
+    name: &'static str,
+    memory: Memory,
+    policy: Box<dyn RestorePolicy>,
+    new_policy: fn() -> Box<dyn RestorePolicy>,
+}
+
+/// The memories of user-defined storage modes of a `Trail`.
+#[derive(Default)]
+pub(crate) struct Regions {
+    regions: Vec<Region>,
+}
+
+impl Regions {
+    /// Returns the index of the memory of the storage mode `M`, which is usually `index`, the
+    /// index it had in the builder.
+    #[inline]
+    fn find<M: StorageMode>(&self, index: usize) -> Option<usize> {
+        let type_id = TypeId::of::<M>();
+        match self.regions.get(index) {
+            Some(region) if region.type_id == type_id => Some(index),
+            _ => self.regions.iter().position(|r| r.type_id == type_id),
+        }
+    }
+
+    /// Returns the memory of the storage mode `M`, whose index in the builder was `index`.
+    #[inline]
+    pub(crate) fn memory<M: StorageMode>(&self, index: usize) -> &Memory {
+        match self.find::<M>(index) {
+            Some(index) => &self.regions[index].memory,
+            None => no_memory::<M>(),
+        }
+    }
+
+    /// Returns the memory of the storage mode `M` mutably, whose index in the builder was
+    /// `index`.
+    #[inline]
+    pub(crate) fn memory_mut<M: StorageMode>(&mut self, index: usize) -> &mut Memory {
+        match self.find::<M>(index) {
+            Some(index) => &mut self.regions[index].memory,
+            None => no_memory::<M>(),
+        }
+    }
+
+    /// Returns builders with copies of the memories, so that values can be added to them and to
+    /// new memories, which are then added back with [`append`](Regions::append).
+    pub(crate) fn to_builders(&self) -> RegionBuilders {
+        RegionBuilders {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| RegionBuilder {
+                    type_id: region.type_id,
+                    name: region.name,
+                    memory: region.memory.to_builder(),
+                    new_policy: region.new_policy,
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the values added to the builders to the memories, creating new memories as needed.
+    pub(crate) fn append(&mut self, builders: RegionBuilders) {
+        for builder in builders.regions {
+            match self
+                .regions
+                .iter_mut()
+                .find(|r| r.type_id == builder.type_id)
+            {
+                Some(region) => region.memory.append(&builder.memory),
+                None => self.regions.push(Region {
+                    type_id: builder.type_id,
+                    name: builder.name,
+                    memory: builder.memory.finish(),
+                    policy: (builder.new_policy)(),
+                    new_policy: builder.new_policy,
+                }),
+            }
+        }
+    }
+
+    /// Returns copies of the memories with new policies, for a trail with no levels.
+    pub(crate) fn fork(&self) -> Regions {
+        Regions {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| Region {
+                    type_id: region.type_id,
+                    name: region.name,
+                    memory: region.memory.clone(),
+                    policy: (region.new_policy)(),
+                    new_policy: region.new_policy,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the name and memory of each storage mode.
+    pub(crate) fn memories(&self) -> impl Iterator<Item = (&'static str, &Memory)> + '_ {
+        self.regions
+            .iter()
+            .map(|region| (region.name, &region.memory))
+    }
+
+    /// Resets the write counters of the memories.
+    pub(crate) fn reset_writes(&mut self) {
+        for region in &mut self.regions {
+            region.memory.reset_writes();
+        }
+    }
+
+    /// Tells every policy that a level was added.
+    pub(crate) fn new_level(&mut self, depth: usize) {
+        for region in &mut self.regions {
+            region.policy.new_level(&mut region.memory, depth);
+        }
+    }
+
+    /// Tells every policy that levels were removed by backtracking.
+    pub(crate) fn backtrack(&mut self, depth: usize) {
+        for region in &mut self.regions {
+            region.policy.backtrack(&mut region.memory, depth);
+        }
+    }
+
+    /// Tells every policy that the most recent level was committed.
+    pub(crate) fn commit_level(&mut self, depth: usize) {
+        for region in &mut self.regions {
+            region.policy.commit_level(&mut region.memory, depth);
+        }
+    }
+}
+
+#[cold]
+fn no_memory<M: StorageMode>() -> ! {
+    panic!(
+        "the trail has no memory for the storage mode `{}`, since no value was created with it",
+        M::NAME
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{watch::EventKind, Array, Strategy, Value};
+
+    #[derive(Clone, Copy, Debug, Default)]
+    struct Logged;
+
+    impl StorageMode for Logged {
+        const NAME: &'static str = "Logged";
+        type Policy = Undo;
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
+    struct Kept;
+
+    impl StorageMode for Kept {
+        const NAME: &'static str = "Kept";
+        type Policy = Keep;
+    }
+
+    #[test]
+    fn undo() {
+        let mut builder = TrailBuilder::with_strategy(Strategy::Copy);
+        let logged = Array::<Logged, u8>::new(&mut builder, vec![0; 3]);
+        let kept = Value::<Kept, u8>::new(&mut builder, 0);
+        let mut trail = builder.finish();
+
+        // writes at the root aren't recorded
+        logged.set(&mut trail, 0, 1);
+        assert_eq!(
+            <Logged as StorageMode>::memory(&trail, 0).journal().mark(),
+            0
+        );
+
+        let first = trail.new_level();
+        logged.set(&mut trail, 1, 1);
+        trail.new_level();
+        logged.set(&mut trail, 2, 1);
+        kept.set(&mut trail, 1);
+
+        // a committed level is undone along with its parent
+        trail.new_level();
+        logged.set(&mut trail, 0, 2);
+        trail.commit_level();
+        assert_eq!(logged.iter(&trail).collect::<Vec<_>>(), vec![2, 1, 1]);
+
+        trail.backtrack();
+        assert_eq!(logged.iter(&trail).collect::<Vec<_>>(), vec![1, 1, 0]);
+
+        trail.backtrack_to(first);
+        assert_eq!(logged.iter(&trail).collect::<Vec<_>>(), vec![1, 0, 0]);
+        assert_eq!(kept.get(&trail), 1);
+
+        // committing the only level keeps every change
+        trail.new_level();
+        logged.set(&mut trail, 2, 3);
+        trail.commit_level();
+        assert_eq!(logged.get(&trail, 2), 3);
+        assert_eq!(
+            <Logged as StorageMode>::memory(&trail, 0).journal().mark(),
+            0
+        );
+    }
+
+    #[test]
+    fn extend() {
+        let mut builder = TrailBuilder::new();
+        let logged = Value::<Logged, u8>::new(&mut builder, 1);
+        let mut trail = builder.finish();
+
+        let (more, kept) = trail.extend(|builder| {
+            (
+                Value::<Logged, u8>::new(builder, 2),
+                Value::<Kept, u8>::new(builder, 3),
+            )
+        });
+
+        assert_eq!(logged.get(&trail), 1);
+        assert_eq!(more.get(&trail), 2);
+        assert_eq!(kept.get(&trail), 3);
+
+        trail.new_level();
+        more.set(&mut trail, 20);
+        trail.backtrack();
+        assert_eq!(more.get(&trail), 2);
+    }
+
+    #[test]
+    fn stats() {
+        let mut builder = TrailBuilder::with_strategy(Strategy::Copy);
+        let logged = Array::<Logged, u8>::new(&mut builder, vec![0; 3]);
+        let kept = Value::<Kept, u8>::new(&mut builder, 0);
+        let restart = Value::<Restart, u8>::new(&mut builder, 0);
+        let mut trail = builder.finish();
+
+        trail.new_level();
+        logged.set(&mut trail, 0, 1);
+        logged.set(&mut trail, 1, 1);
+        kept.set(&mut trail, 1);
+        restart.set(&mut trail, 1);
+
+        // only the undo log of the `Undo` policy holds bytes, since there's no backtrackable memory
+        let stats = trail.stats();
+        assert_eq!(
+            stats.level_stack_bytes,
+            <Logged as StorageMode>::memory(&trail, 0).journal().size()
+        );
+        assert!(stats.level_stack_bytes >= 2);
+        let writes = stats.writes.into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            writes,
+            vec![
+                ("Backtrackable", 0),
+                ("Kept", 1),
+                ("Logged", 2),
+                ("NonBacktrackable", 0),
+                ("Restart", 1),
+            ]
+        );
+
+        trail.backtrack();
+        assert_eq!(trail.stats().level_stack_bytes, 0);
+
+        trail.reset_stats();
+        assert!(trail.stats().writes.values().all(|&writes| writes == 0));
+    }
+
+    #[test]
+    fn region() {
+        let mut builder = TrailBuilder::new();
+        let kept = Value::<Kept, u8>::new(&mut builder, 1);
+        let logged = Array::<Logged, u8>::new(&mut builder, vec![2; 3]);
+        assert_eq!(kept.region, 0);
+        assert_eq!(logged.region, 1);
+        let trail = builder.finish();
+
+        // the memory is searched for if the region is wrong
+        assert_eq!(trail.regions.find::<Logged>(1), Some(1));
+        assert_eq!(trail.regions.find::<Logged>(0), Some(1));
+        assert_eq!(trail.regions.find::<Logged>(5), Some(1));
+        assert_eq!(
+            <Logged as StorageMode>::memory(&trail, 0).as_slice(),
+            &[2; 3]
+        );
+    }
+
+    #[test]
+    fn watch_same_name() {
+        #[derive(Clone, Copy, Debug, Default)]
+        struct AlsoKept;
+
+        impl StorageMode for AlsoKept {
+            const NAME: &'static str = "Kept";
+            type Policy = Keep;
+        }
+
+        let mut builder = TrailBuilder::new();
+        let kept = Value::<Kept, u8>::new(&mut builder, 1);
+        let also_kept = Value::<AlsoKept, u8>::new(&mut builder, 2);
+        let mut trail = builder.finish();
+
+        let watch = kept.watch(&mut trail, EventKind::Write);
+        also_kept.set(&mut trail, 3);
+        assert_eq!(trail.next_event(), None);
+        kept.set(&mut trail, 3);
+        assert_eq!(trail.next_event().unwrap().watch, watch);
+    }
+
+    #[test]
+    #[should_panic(expected = "the trail has no memory for the storage mode `Kept`")]
+    fn no_memory() {
+        let trail = TrailBuilder::new().finish();
+        <Kept as StorageMode>::memory(&trail, 0);
+    }
+}
@@ -0,0 +1,246 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public License,
+ * v. 2.0. If a copy of the MPL was not distributed with this file, You can
+ * obtain one at http://mozilla.org/MPL/2.0/.
+ */
+
+//! Notifications of writes to values on the trail.
+//!
+//! A [`Value`](crate::Value) or an element of an [`Array`](crate::Array) can be watched with
+//! [`Value::watch`](crate::Value::watch) or [`Array::watch`](crate::Array::watch). Whenever a
+//! watched value is written through its handle, the trail adds an [`Event`](Event) to a queue,
+//! which can be consumed with [`Trail::next_event`](crate::Trail::next_event) or
+//! [`Trail::drain_events`](crate::Trail::drain_events). This is the usual building block of a
+//! propagation engine: each watch identifies a propagator to schedule when a variable changes.
+//!
+//! Watching is optional: while nothing is watched, writes cost a single extra check.
+//!
+//! Only writes made through handles are observed. Restoring memory by backtracking or by
+//! [restoring a snapshot](crate::Trail::restore) doesn't add events, and backtracking doesn't
+//! remove events from the queue.
+//!
+//! A watch on an [allocated](crate::Value::alloc) value stops adding events once the value is
+//! freed, even if its memory is later reused by another allocated value.
+//!
+//! # Examples
+//!
+//! ```
+//! use contrail::{watch::EventKind, BacktrackableArray, TrailBuilder};
+//!
+//! let mut builder = TrailBuilder::new();
+//! let domains = BacktrackableArray::new(&mut builder, vec![0b1111u8; 3]);
+//! let mut trail = builder.finish();
+//!
+//! let watch = domains.watch(&mut trail, 1, EventKind::Change);
+//!
+//! domains.set(&mut trail, 0, 0b0011);
+//! domains.set(&mut trail, 1, 0b1111);
+//! assert_eq!(trail.next_event(), None);
+//!
+//! domains.update(&mut trail, 1, |domain| domain & 0b0110);
+//! let event = trail.next_event().unwrap();
+//! assert_eq!(event.watch, watch);
+//! assert_eq!(event.kind, EventKind::Change);
+//! ```
+
+use std::{
+    any::TypeId,
+    collections::{hash_map::Entry, HashMap, VecDeque},
+};
+
+use crate::{mem::Generation, storage::Readable};
+
+/// An identifier of a watch, returned when a value is watched.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct WatchId(u64);
+
+/// The writes that a watch reports.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EventKind {
+    /// Every write to the value, even if the value stays the same.
+    Write,
+    /// Only writes that change the value.
+    Change,
+}
+
+/// A write to a watched value.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Event {
+    /// The watch that observed the write.
+    pub watch: WatchId,
+    /// The kind of the watch that observed the write.
+    pub kind: EventKind,
+}
+
+/// The location of a watched value.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct Location {
+    /// The value's storage mode, which tells apart storage modes with the same name.
+    mode: TypeId,
+    /// The region of the storage mode's memory.
+    region: usize,
+    /// The generation of the value's allocation, or `None` if the value wasn't allocated.
+    generation: Option<Generation>,
+    /// The offset of the value in memory.
+    offset: usize,
+}
+
+impl Location {
+    /// Creates the location of a value at the given offset in the memory of the storage mode
+    /// `M` in the given region.
+    pub(crate) fn new<M>(region: usize, generation: Option<Generation>, offset: usize) -> Self
+    where
+        M: Readable,
+    {
+        Location {
+            mode: TypeId::of::<M>(),
+            region,
+            generation,
+            offset,
+        }
+    }
+
+    /// Returns the offset of the value in memory.
+    pub(crate) fn offset(self) -> usize {
+        self.offset
+    }
+
+    /// Returns the location of the value `distance` bytes after this value in the same memory.
+    pub(crate) fn add(self, distance: usize) -> Self {
+        Location {
+            offset: self.offset + distance,
+            ..self
+        }
+    }
+}
+
+/// The watches of a trail and the queue of events they fired.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Watchers {
+    next_id: u64,
+    watches: HashMap<Location, Vec<(WatchId, EventKind)>>,
+    locations: HashMap<WatchId, Location>,
+    queue: VecDeque<Event>,
+}
+
+impl Watchers {
+    /// Checks if nothing is watched.
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Watches the value at the given location.
+    pub(crate) fn watch(&mut self, location: Location, kind: EventKind) -> WatchId {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        self.watches.entry(location).or_default().push((id, kind));
+        self.locations.insert(id, location);
+        id
+    }
+
+    /// Stops a watch, returning `false` if it was already stopped.
+    pub(crate) fn unwatch(&mut self, id: WatchId) -> bool {
+        let location = match self.locations.remove(&id) {
+            Some(location) => location,
+            None => return false,
+        };
+        if let Entry::Occupied(mut entry) = self.watches.entry(location) {
+            entry.get_mut().retain(|&(watch, _)| watch != id);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+        true
+    }
+
+    /// Checks if the value at the given location is watched.
+    pub(crate) fn is_watched(&self, location: Location) -> bool {
+        self.watches.contains_key(&location)
+    }
+
+    /// Adds an event for every watch of the value at the given location that reports the write.
+    pub(crate) fn notify(&mut self, location: Location, changed: bool) {
+        if let Some(watches) = self.watches.get(&location) {
+            self.queue.extend(
+                watches
+                    .iter()
+                    .filter(|&&(_, kind)| changed || kind == EventKind::Write)
+                    .map(|&(watch, kind)| Event { watch, kind }),
+            );
+        }
+    }
+
+    /// Returns the queue of events, oldest first.
+    pub(crate) fn queue_mut(&mut self) -> &mut VecDeque<Event> {
+        &mut self.queue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct A;
+    struct B;
+
+    fn at<M: 'static>(offset: usize) -> Location {
+        Location {
+            mode: TypeId::of::<M>(),
+            region: 0,
+            generation: None,
+            offset,
+        }
+    }
+
+    #[test]
+    fn notify() {
+        let mut watchers = Watchers::default();
+        let write = watchers.watch(at::<A>(0), EventKind::Write);
+        let change = watchers.watch(at::<A>(0), EventKind::Change);
+        let other = watchers.watch(at::<B>(0), EventKind::Write);
+
+        watchers.notify(at::<A>(0), false);
+        watchers.notify(at::<A>(0), true);
+        watchers.notify(at::<A>(8), true);
+        watchers.notify(at::<B>(0), true);
+
+        let events = watchers.queue_mut().drain(..).collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                Event {
+                    watch: write,
+                    kind: EventKind::Write
+                },
+                Event {
+                    watch: write,
+                    kind: EventKind::Write
+                },
+                Event {
+                    watch: change,
+                    kind: EventKind::Change
+                },
+                Event {
+                    watch: other,
+                    kind: EventKind::Write
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unwatch() {
+        let mut watchers = Watchers::default();
+        let first = watchers.watch(at::<A>(0), EventKind::Write);
+        let second = watchers.watch(at::<A>(0), EventKind::Write);
+
+        assert!(watchers.unwatch(first));
+        assert!(!watchers.unwatch(first));
+        assert!(watchers.is_watched(at::<A>(0)));
+
+        assert!(watchers.unwatch(second));
+        assert!(!watchers.is_watched(at::<A>(0)));
+        assert!(watchers.is_empty());
+    }
+}
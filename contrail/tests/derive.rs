@@ -25,3 +25,19 @@ fn custom_derive() {
     unsafe { foo.write_bytes(&mut bytes) };
     assert_eq!(unsafe { Foo::read_bytes(&bytes) }, foo);
 }
+
+#[test]
+fn custom_derive_in_place() {
+    use contrail::{BacktrackableArray, BacktrackableValue, TrailBuilder};
+
+    let foo = |a| Foo { a, b: [0, 1, 2] };
+
+    let mut builder = TrailBuilder::new();
+    let flag = BacktrackableValue::new(&mut builder, true);
+    let foos = BacktrackableArray::new(&mut builder, vec![foo(1), foo(2)]);
+    let mut trail = builder.finish();
+
+    foos.set(&mut trail, 1, foo(3));
+    assert!(flag.get(&trail));
+    assert_eq!(foos.as_slice(&trail), &[foo(1), foo(3)]);
+}